@@ -0,0 +1,37 @@
+use aoc2019::intcode::compile::Compiled;
+use aoc2019::intcode::{asm, IntCode};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// Reads n and prints fib(n) by naive recursion, the same shape of workload as the day 9
+// part 2 BOOST program.
+const FIB: &str = include_str!("../src/intcode/fixtures/fib.asm");
+
+fn fib(program: &[isize], cached: bool) -> isize {
+    let mut machine = IntCode::new(program);
+    machine.set_decode_cache(cached);
+    machine.push_input(20);
+    let output = machine.run_to_halt().unwrap();
+    assert_eq!(output, vec![6765]);
+    output[0]
+}
+
+fn fib_compiled(compiled: &mut Compiled) -> isize {
+    compiled.reset();
+    compiled.push_input(20);
+    let output = compiled.run_to_halt().unwrap();
+    assert_eq!(output, vec![6765]);
+    output[0]
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let program = asm::assemble(FIB).unwrap();
+    let mut group = c.benchmark_group("recursive fib(20)");
+    group.bench_function("decode cache", |b| b.iter(|| fib(&program, true)));
+    group.bench_function("no cache", |b| b.iter(|| fib(&program, false)));
+    let mut compiled = Compiled::new(&program);
+    group.bench_function("compiled", |b| b.iter(|| fib_compiled(&mut compiled)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
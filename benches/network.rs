@@ -0,0 +1,34 @@
+use aoc2019::intcode::{IntCode, Network, NetworkState, Topology};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const FEEDBACK: &[isize] = &[
+    3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005,
+    28, 6, 99, 0, 0, 5,
+];
+
+fn feedback_loop(threaded: bool) -> isize {
+    let phases = [9, 8, 7, 6, 5];
+    let machines = phases.iter().map(|_| IntCode::new(FEEDBACK)).collect();
+    let mut network = Network::new(machines, Topology::Ring);
+    for (node, phase) in phases.iter().enumerate() {
+        network.push_input(node, *phase);
+    }
+    network.push_input(0, 0);
+    let state = if threaded {
+        network.run_threaded()
+    } else {
+        network.run()
+    };
+    assert_eq!(state.unwrap(), NetworkState::Halted);
+    network.last_output(4).unwrap()
+}
+
+fn bench_feedback(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day7 feedback loop");
+    group.bench_function("cooperative", |b| b.iter(|| feedback_loop(false)));
+    group.bench_function("threaded", |b| b.iter(|| feedback_loop(true)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_feedback);
+criterion_main!(benches);
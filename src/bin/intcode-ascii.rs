@@ -0,0 +1,52 @@
+use aoc2019::intcode::ascii::AsciiIo;
+use aoc2019::intcode::{parse_program, IntCode, RunState};
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-ascii <program>");
+            process::exit(2);
+        }
+    };
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let program = parse_program(&text).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut machine = IntCode::new(&program);
+    let mut io = AsciiIo::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let state = machine.run_io(&mut io);
+        for chunk in io.take_output() {
+            print!("{}", chunk);
+        }
+        io::stdout().flush().unwrap();
+        match state {
+            Ok(RunState::NeedInput) => {}
+            Ok(_) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        // Prompt until the program gets a line it can read; end of input ends the session.
+        loop {
+            print!("> ");
+            io::stdout().flush().unwrap();
+            match lines.next() {
+                Some(Ok(line)) if io.push_line(&line) => break,
+                Some(Ok(_)) => eprintln!("input must be ASCII"),
+                Some(Err(_)) | None => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use aoc2019::intcode::debugger::{Debugger, Stop};
+use aoc2019::intcode::{parse_program, Instruction};
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
+
+const HELP: &str = "\
+s [n]          step n instructions (default 1)
+c              continue until a breakpoint, watchpoint, halt or blocked input
+b <ip>         set a breakpoint          db <ip>   delete a breakpoint
+w <addr>       watch a memory address    dw <addr> delete a watchpoint
+r              show registers
+m <addr> [n]   dump n words of memory (default 16)
+l [ip] [n]     disassemble n instructions (default: 8 from the current ip)
+i <v>...       append values to the input queue
+iq             show the input queue
+ic             clear the input queue
+o              show all output so far
+p [n]          profile: opcode counts and the n hottest addresses (default 10)
+q              quit";
+
+fn describe(stop: Stop) -> String {
+    match stop {
+        Stop::Step => String::new(),
+        Stop::Breakpoint(ip) => format!("breakpoint at {:04}", ip),
+        Stop::Watchpoint { address, old, new } => {
+            format!("watchpoint {:04}: {} -> {}", address, old, new)
+        }
+        Stop::Output(x) => format!("output {}", x),
+        Stop::NeedInput => "blocked on input".to_string(),
+        Stop::Halted => "halted".to_string(),
+    }
+}
+
+fn parse_args<T: std::str::FromStr>(args: &[&str]) -> Result<Vec<T>, String> {
+    args.iter()
+        .map(|x| x.parse().map_err(|_| format!("bad number '{}'", x)))
+        .collect()
+}
+
+fn disassemble(debugger: &Debugger, start: usize, count: usize) -> String {
+    let memory = debugger.machine().memory();
+    let mut out = String::new();
+    let mut ip = start;
+    for _ in 0..count {
+        let marker = if ip == debugger.machine().ip() {
+            '>'
+        } else {
+            ' '
+        };
+        match Instruction::decode(ip, |a| memory[a]) {
+            Ok(instruction) => {
+                out.push_str(&format!("{}{:04}: {}\n", marker, ip, instruction));
+                ip = match ip.checked_add(instruction.size()) {
+                    Some(ip) => ip,
+                    None => break,
+                };
+            }
+            Err(_) => {
+                out.push_str(&format!("{}{:04}: DATA {}\n", marker, ip, memory[ip]));
+                ip = match ip.checked_add(1) {
+                    Some(ip) => ip,
+                    None => break,
+                };
+            }
+        }
+    }
+    out
+}
+
+fn command(debugger: &mut Debugger, line: &str) -> Result<String, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (*name, args),
+        None => return Ok(String::new()),
+    };
+    let numbers = parse_args::<usize>(args);
+    match name {
+        "h" | "help" => Ok(HELP.to_string()),
+        "s" => {
+            let n = numbers?.first().cloned().unwrap_or(1);
+            let mut out = Vec::new();
+            for _ in 0..n {
+                let stop = debugger.step().map_err(|e| e.to_string())?;
+                if stop != Stop::Step {
+                    out.push(describe(stop));
+                }
+                if let Stop::Step | Stop::Output(_) = stop {
+                    continue;
+                }
+                break;
+            }
+            out.push(debugger.registers());
+            Ok(out.join("\n"))
+        }
+        "c" => {
+            let stop = debugger.resume().map_err(|e| e.to_string())?;
+            Ok(format!("{}\n{}", describe(stop), debugger.registers()))
+        }
+        "b" | "db" | "w" | "dw" => {
+            let address = match numbers?.first() {
+                Some(address) => *address,
+                None => return Err(format!("{} needs an address", name)),
+            };
+            let changed = match name {
+                "b" => debugger.add_breakpoint(address),
+                "db" => debugger.remove_breakpoint(address),
+                "w" => debugger.add_watchpoint(address),
+                _ => debugger.remove_watchpoint(address),
+            };
+            Ok(format!(
+                "{}breakpoints {:?} watchpoints {:?}",
+                if changed { "" } else { "(unchanged) " },
+                debugger.breakpoints(),
+                debugger.watchpoints()
+            ))
+        }
+        "r" => Ok(debugger.registers()),
+        "m" => {
+            let numbers = numbers?;
+            let start = match numbers.first() {
+                Some(start) => *start,
+                None => return Err("m needs an address".to_string()),
+            };
+            let len = numbers.get(1).cloned().unwrap_or(16);
+            if start.checked_add(len).is_none() {
+                return Err("address range is too large".to_string());
+            }
+            Ok(debugger.dump(start, len).trim_end().to_string())
+        }
+        "l" => {
+            let numbers = numbers?;
+            let start = numbers
+                .first()
+                .cloned()
+                .unwrap_or_else(|| debugger.machine().ip());
+            let count = numbers.get(1).cloned().unwrap_or(8);
+            Ok(disassemble(debugger, start, count).trim_end().to_string())
+        }
+        "i" => {
+            let values = parse_args::<isize>(args)?;
+            debugger.machine_mut().extend_input(&values);
+            Ok(format!("input {:?}", debugger.machine().input_queue()))
+        }
+        "iq" => Ok(format!("input {:?}", debugger.machine().input_queue())),
+        "ic" => {
+            debugger.machine_mut().input_queue_mut().clear();
+            Ok("input []".to_string())
+        }
+        "o" => Ok(format!("output {:?}", debugger.output())),
+        "p" => {
+            let n = numbers?.first().cloned().unwrap_or(10);
+            let profile = debugger.machine().profile().unwrap();
+            Ok(profile.report(n).trim_end().to_string())
+        }
+        _ => Err(format!("unknown command '{}', try 'help'", name)),
+    }
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-debug <program>");
+            process::exit(2);
+        }
+    };
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let program = parse_program(&text).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut debugger = Debugger::new(&program);
+    debugger.machine_mut().set_profiling(true);
+    println!("{}", debugger.registers());
+    let stdin = io::stdin();
+    loop {
+        print!("(icd) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line == "q" || line == "quit" {
+            break;
+        }
+        match command(&mut debugger, line) {
+            Ok(out) if out.is_empty() => {}
+            Ok(out) => println!("{}", out),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
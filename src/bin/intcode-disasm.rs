@@ -0,0 +1,31 @@
+use aoc2019::intcode::cfg::Cfg;
+use aoc2019::intcode::{disasm, parse_program};
+use std::{env, fs, process};
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let dot = args.first().map(|x| x.as_str()) == Some("--dot");
+    if dot {
+        args.remove(0);
+    }
+    let path = match args.first() {
+        Some(path) => path.clone(),
+        None => {
+            eprintln!("usage: intcode-disasm [--dot] <program>");
+            process::exit(2);
+        }
+    };
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let program = parse_program(&text).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    if dot {
+        print!("{}", Cfg::new(&program).to_dot());
+    } else {
+        print!("{}", disasm::listing(&program));
+    }
+}
@@ -1,160 +1,1245 @@
-enum Arg {
-    Immediate(isize),
-    Parameter(usize),
-}
-
-impl Arg {
-    fn new(instruction: isize, arg: isize, position: usize) -> Arg {
-        let divisor = match position {
-            1 => 100,
-            2 => 1000,
-            3 => 10000,
-            _ => unimplemented!(),
-        };
-        match (instruction / divisor) % 10 {
-            0 => Arg::Immediate(arg),
-            1 => Arg::Parameter(arg as usize),
-            _ => unimplemented!(),
-        }
-    }
-    fn resolve_in(&self, memory: &[isize]) -> isize {
-        match *self {
-            Arg::Immediate(x) => x,
-            Arg::Parameter(x) => memory[x],
-        }
-    }
-    fn resolve_out(&self) -> usize {
-        match *self {
-            Arg::Immediate(x) => unimplemented!(),
-            Arg::Parameter(x) => x,
-        }
-    }
-}
-
-enum Instruction {
-    Halt,
-    Add(Arg, Arg, Arg),
-    Mul(Arg, Arg, Arg),
-    Input(Arg),
-    Output(Arg),
-    JNZ(Arg, Arg),
-    JZ(Arg, Arg),
-    LessThan(Arg, Arg, Arg),
-    Equals(Arg, Arg, Arg),
-}
-
-impl Instruction {
-    fn arg_count(&self) -> usize {
-        match self {
-            Instruction::Halt => 0,
-            Instruction::Add(_, _, _) => 3,
-            Instruction::Mul(_, _, _) => 3,
-            Instruction::Input(_) => 1,
-            Instruction::Output(_) => 1,
-            Instruction::JNZ(_, _) => 2,
-            Instruction::JZ(_, _) => 2,
-            Instruction::LessThan(_, _, _) => 3,
-            Instruction::Equals(_, _, _) => 3,
-        }
-    }
-}
-
-pub struct IntCode {
-    memory: Vec<isize>,
-    ip: usize,
-    input_index: usize,
-    halted: bool,
-}
-
-impl IntCode {
-    pub fn new(input: &[isize]) -> IntCode {
-        IntCode {
-            memory: input.to_vec(),
-            ip: 0,
-            input_index: 0,
-            halted: false,
-        }
-    }
-
-    pub fn decode(&self) -> Instruction {
-        let inst = self.memory[self.ip];
-        let arg1 = Arg::new(inst, self.memory[self.ip + 1], 1);
-        let arg2 = Arg::new(inst, self.memory[self.ip + 2], 2);
-        let arg3 = Arg::new(inst, self.memory[self.ip + 3], 3);
-        match inst % 100 {
-            99 => Instruction::Halt,
-            1 => Instruction::Add(arg1, arg2, arg3),
-            2 => Instruction::Mul(arg1, arg2, arg3),
-            3 => Instruction::Input(arg1),
-            4 => Instruction::Output(arg1),
-            5 => Instruction::JNZ(arg1, arg2),
-            6 => Instruction::JZ(arg1, arg2),
-            7 => Instruction::LessThan(arg1, arg2, arg3),
-            8 => Instruction::Equals(arg1, arg2, arg3),
-            _ => unimplemented!(),
-        }
-    }
-
-    pub fn execute(&mut self, instruction: &Instruction, input: &[isize], output: &mut Vec<isize>) {
-        match instruction {
-            Instruction::Halt => {
-                self.halted = true;
-                return;
-            }
-            Instruction::Add(x, y, z) => {
-                let x = x.resolve_in(&self.memory);
-                let y = y.resolve_in(&self.memory);
-                let z = z.resolve_out();
-                self.memory[z] = x + y;
-            }
-            Instruction::Mul(x, y, z) => {
-                let x = x.resolve_in(&self.memory);
-                let y = y.resolve_in(&self.memory);
-                let z = z.resolve_out();
-                self.memory[z] = x * y;
-            }
-            Instruction::Input(x) => {
-                if self.input_index >= input.len() {
-                    // Stall waiting for input.
-                    return;
-                }
-                let x = x.resolve_out();
-                self.memory[x] = input[self.input_index];
-                self.input_index += 1;
-            }
-            Instruction::Output(x) => {
-                let x = x.resolve_in(&self.memory);
-                output.push(x);
-            }
-            Instruction::JNZ(x, y) => {
-                let x = x.resolve_in(&self.memory);
-                let y = y.resolve_in(&self.memory);
-                if x != 0 {
-                    self.ip = y as usize;
-                    return;
-                }
-            }
-            Instruction::JZ(x, y) => {
-                let x = x.resolve_in(&self.memory);
-                let y = y.resolve_in(&self.memory);
-                if x == 0 {
-                    self.ip = y as usize;
-                    return;
-                }
-            }
-            Instruction::LessThan(x, y, z) => {
-                let x = x.resolve_in(&self.memory);
-                let y = y.resolve_in(&self.memory);
-                let z = z.resolve_out();
-                self.memory[z] = if x < y { 1 } else { 0 };
-            }
-            Instruction::Equals(x, y, z) => {
-                let x = x.resolve_in(&self.memory);
-                let y = y.resolve_in(&self.memory);
-                let z = z.resolve_out();
-                self.memory[z] = if x == y { 1 } else { 0 };
-            }
-        }
-        self.ip += instruction.arg_count();
-    }
-}
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Instant;
+
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod compile;
+pub mod debugger;
+pub mod disasm;
+#[cfg(test)]
+mod fixtures;
+mod io;
+mod memory;
+mod network;
+mod profile;
+mod selfmod;
+mod snapshot;
+pub mod symbolic;
+pub mod trace;
+mod word;
+
+pub use self::io::{ChannelIo, FnIo, IntcodeIo, QueueIo};
+pub use self::memory::{Memory, DEFAULT_MAX_SIZE};
+pub use self::network::{Network, NetworkState, Topology};
+pub use self::profile::Profile;
+pub use self::selfmod::{CodeWrite, SelfModReport};
+pub use self::trace::{TraceEvent, TraceSink};
+pub use self::word::Word;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        ip: usize,
+        instruction: isize,
+    },
+    InvalidMode {
+        ip: usize,
+        instruction: isize,
+        mode: isize,
+    },
+    ImmediateWrite {
+        ip: usize,
+        instruction: isize,
+    },
+    NegativeAddress {
+        ip: usize,
+        instruction: isize,
+        address: isize,
+    },
+    IpOverflow {
+        ip: usize,
+        instruction: isize,
+    },
+    // The machine was left with ip outside memory, so there's no instruction to report.
+    IpOutOfRange {
+        ip: usize,
+    },
+    MemoryLimit {
+        ip: usize,
+        instruction: isize,
+        address: usize,
+    },
+    InputExhausted {
+        ip: usize,
+        instruction: isize,
+    },
+    ArithmeticOverflow {
+        ip: usize,
+        instruction: isize,
+    },
+    // A word used as an opcode, address or immediate that doesn't fit in an isize.
+    WordRange {
+        ip: usize,
+        address: usize,
+    },
+    // A step limit or deadline ran out. The machine is intact and can carry on running.
+    LimitExceeded {
+        ip: usize,
+    },
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IntcodeError::UnknownOpcode { ip, instruction } => {
+                write!(f, "unknown opcode {} at ip {}", instruction, ip)
+            }
+            IntcodeError::InvalidMode {
+                ip,
+                instruction,
+                mode,
+            } => write!(
+                f,
+                "invalid parameter mode {} in instruction {} at ip {}",
+                mode, instruction, ip
+            ),
+            IntcodeError::ImmediateWrite { ip, instruction } => write!(
+                f,
+                "write through immediate parameter in instruction {} at ip {}",
+                instruction, ip
+            ),
+            IntcodeError::NegativeAddress {
+                ip,
+                instruction,
+                address,
+            } => write!(
+                f,
+                "negative address {} in instruction {} at ip {}",
+                address, instruction, ip
+            ),
+            IntcodeError::IpOverflow { ip, instruction } => write!(
+                f,
+                "instruction pointer left memory after instruction {} at ip {}",
+                instruction, ip
+            ),
+            IntcodeError::IpOutOfRange { ip } => {
+                write!(f, "instruction pointer {} is outside memory", ip)
+            }
+            IntcodeError::MemoryLimit {
+                ip,
+                instruction,
+                address,
+            } => write!(
+                f,
+                "address {} beyond memory limit in instruction {} at ip {}",
+                address, instruction, ip
+            ),
+            IntcodeError::InputExhausted { ip, instruction } => write!(
+                f,
+                "ran out of input in instruction {} at ip {}",
+                instruction, ip
+            ),
+            IntcodeError::ArithmeticOverflow { ip, instruction } => write!(
+                f,
+                "arithmetic overflow in instruction {} at ip {}",
+                instruction, ip
+            ),
+            IntcodeError::WordRange { ip, address } => write!(
+                f,
+                "word at address {} is out of range for the instruction at ip {}",
+                address, ip
+            ),
+            IntcodeError::LimitExceeded { ip } => {
+                write!(f, "step limit or deadline exceeded at ip {}", ip)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntcodeError {}
+
+// How many steps run between checks of the wall clock against a deadline.
+const DEADLINE_INTERVAL: u64 = 1024;
+
+pub fn parse_program(input: &str) -> Result<Vec<isize>, std::num::ParseIntError> {
+    input.split(',').map(|x| x.trim().parse()).collect()
+}
+
+// Immediates are full words; addresses and relative offsets always fit an isize.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arg<W = isize> {
+    Immediate(W),
+    Parameter(isize),
+    Relative(isize),
+}
+
+impl<W: Word> Arg<W> {
+    fn new(ip: usize, instruction: isize, arg: W, position: u32) -> Result<Arg<W>, IntcodeError> {
+        let address = || {
+            arg.to_isize().ok_or(IntcodeError::WordRange {
+                ip,
+                address: ip.saturating_add(position as usize),
+            })
+        };
+        match (instruction / 10isize.pow(position + 1)) % 10 {
+            0 => Ok(Arg::Parameter(address()?)),
+            1 => Ok(Arg::Immediate(arg)),
+            2 => Ok(Arg::Relative(address()?)),
+            mode => Err(IntcodeError::InvalidMode {
+                ip,
+                instruction,
+                mode,
+            }),
+        }
+    }
+
+    fn mode(&self) -> isize {
+        match self {
+            Arg::Parameter(_) => 0,
+            Arg::Immediate(_) => 1,
+            Arg::Relative(_) => 2,
+        }
+    }
+
+    pub fn value(&self) -> W {
+        match *self {
+            Arg::Immediate(x) => x,
+            Arg::Parameter(x) | Arg::Relative(x) => W::from_isize(x),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction<W = isize> {
+    Halt,
+    Add(Arg<W>, Arg<W>, Arg<W>),
+    Mul(Arg<W>, Arg<W>, Arg<W>),
+    Input(Arg<W>),
+    Output(Arg<W>),
+    JNZ(Arg<W>, Arg<W>),
+    JZ(Arg<W>, Arg<W>),
+    LessThan(Arg<W>, Arg<W>, Arg<W>),
+    Equals(Arg<W>, Arg<W>, Arg<W>),
+    AdjustRelativeBase(Arg<W>),
+}
+
+impl<W: Word> Instruction<W> {
+    // Decodes the instruction at `ip`, reading memory through `fetch`. Only the words the
+    // opcode actually uses are read.
+    pub fn decode<F: Fn(usize) -> W>(ip: usize, fetch: F) -> Result<Instruction<W>, IntcodeError> {
+        let inst = fetch(ip)
+            .to_isize()
+            .ok_or(IntcodeError::WordRange { ip, address: ip })?;
+        let arg = |n: u32| Arg::new(ip, inst, fetch(ip.saturating_add(n as usize)), n);
+        Ok(match inst % 100 {
+            99 => Instruction::Halt,
+            1 => Instruction::Add(arg(1)?, arg(2)?, arg(3)?),
+            2 => Instruction::Mul(arg(1)?, arg(2)?, arg(3)?),
+            3 => Instruction::Input(arg(1)?),
+            4 => Instruction::Output(arg(1)?),
+            5 => Instruction::JNZ(arg(1)?, arg(2)?),
+            6 => Instruction::JZ(arg(1)?, arg(2)?),
+            7 => Instruction::LessThan(arg(1)?, arg(2)?, arg(3)?),
+            8 => Instruction::Equals(arg(1)?, arg(2)?, arg(3)?),
+            9 => Instruction::AdjustRelativeBase(arg(1)?),
+            _ => {
+                return Err(IntcodeError::UnknownOpcode {
+                    ip,
+                    instruction: inst,
+                })
+            }
+        })
+    }
+
+    pub fn opcode(&self) -> isize {
+        match self {
+            Instruction::Halt => 99,
+            Instruction::Add(_, _, _) => 1,
+            Instruction::Mul(_, _, _) => 2,
+            Instruction::Input(_) => 3,
+            Instruction::Output(_) => 4,
+            Instruction::JNZ(_, _) => 5,
+            Instruction::JZ(_, _) => 6,
+            Instruction::LessThan(_, _, _) => 7,
+            Instruction::Equals(_, _, _) => 8,
+            Instruction::AdjustRelativeBase(_) => 9,
+        }
+    }
+
+    pub fn args(&self) -> Vec<Arg<W>> {
+        match *self {
+            Instruction::Halt => vec![],
+            Instruction::Input(x) | Instruction::Output(x) | Instruction::AdjustRelativeBase(x) => {
+                vec![x]
+            }
+            Instruction::JNZ(x, y) | Instruction::JZ(x, y) => vec![x, y],
+            Instruction::Add(x, y, z)
+            | Instruction::Mul(x, y, z)
+            | Instruction::LessThan(x, y, z)
+            | Instruction::Equals(x, y, z) => vec![x, y, z],
+        }
+    }
+
+    // The words this instruction occupies in memory, in canonical form.
+    pub fn encode(&self) -> Vec<W> {
+        let args = self.args();
+        let mut word = self.opcode();
+        let mut scale = 100;
+        for arg in &args {
+            word += arg.mode() * scale;
+            scale *= 10;
+        }
+        let mut words = vec![W::from_isize(word)];
+        words.extend(args.iter().map(|x| x.value()));
+        words
+    }
+
+    // Index into `args()` of the operand this instruction writes through, if any.
+    pub fn write_arg(&self) -> Option<usize> {
+        match self {
+            Instruction::Input(_) => Some(0),
+            Instruction::Add(_, _, _)
+            | Instruction::Mul(_, _, _)
+            | Instruction::LessThan(_, _, _)
+            | Instruction::Equals(_, _, _) => Some(2),
+            _ => None,
+        }
+    }
+
+    // Number of words occupied, including the opcode.
+    pub fn size(&self) -> usize {
+        1 + self.arg_count()
+    }
+
+    pub fn arg_count(&self) -> usize {
+        match self {
+            Instruction::Halt => 0,
+            Instruction::Add(_, _, _) => 3,
+            Instruction::Mul(_, _, _) => 3,
+            Instruction::Input(_) => 1,
+            Instruction::Output(_) => 1,
+            Instruction::JNZ(_, _) => 2,
+            Instruction::JZ(_, _) => 2,
+            Instruction::LessThan(_, _, _) => 3,
+            Instruction::Equals(_, _, _) => 3,
+            Instruction::AdjustRelativeBase(_) => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult<W = isize> {
+    Continue,
+    Halted,
+    NeedInput,
+    Output(W),
+}
+
+// The observable events that stop `IntCode::run`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState<W = isize> {
+    Halted,
+    NeedInput,
+    Output(W),
+}
+
+pub struct IntCode<W = isize> {
+    memory: Memory<W>,
+    ip: usize,
+    relative_base: isize,
+    input: VecDeque<W>,
+    halted: bool,
+    trap_overflow: bool,
+    tracer: Option<Box<dyn TraceSink<W> + Send>>,
+    last_write: Option<(usize, W)>,
+    // Decoded instructions by address, cleared around every write that could touch them.
+    decoded: Vec<Option<Instruction<W>>>,
+    cache_decoded: bool,
+    self_mod: Option<SelfModReport>,
+    profile: Option<Profile<W>>,
+    deadline: Option<Instant>,
+    step_budget: Option<u64>,
+}
+
+// A clone starts without a tracer; everything else, including pending input, is copied.
+impl<W: Word> Clone for IntCode<W> {
+    fn clone(&self) -> IntCode<W> {
+        IntCode {
+            memory: self.memory.clone(),
+            ip: self.ip,
+            relative_base: self.relative_base,
+            input: self.input.clone(),
+            halted: self.halted,
+            trap_overflow: self.trap_overflow,
+            tracer: None,
+            last_write: None,
+            decoded: self.decoded.clone(),
+            cache_decoded: self.cache_decoded,
+            self_mod: self.self_mod.clone(),
+            profile: self.profile.clone(),
+            deadline: self.deadline,
+            step_budget: self.step_budget,
+        }
+    }
+}
+
+// The `isize` constructors are kept apart from the generic ones so that a program written as
+// integer literals doesn't quietly end up running on `i32` words.
+impl IntCode {
+    pub fn new(input: &[isize]) -> IntCode {
+        IntCode::with_memory_limit(input, DEFAULT_MAX_SIZE)
+    }
+
+    pub fn with_memory_limit(input: &[isize], max_size: usize) -> IntCode {
+        IntCode::from_words(input, max_size)
+    }
+
+    // A machine with `patches` poked over the program. `reset` undoes the patches.
+    pub fn with_patches(
+        program: &[isize],
+        patches: &[(isize, isize)],
+    ) -> Result<IntCode, IntcodeError> {
+        let mut machine = IntCode::new(program);
+        machine.patch(patches)?;
+        Ok(machine)
+    }
+}
+
+impl<W: Word> IntCode<W> {
+    pub fn from_words(program: &[W], max_size: usize) -> IntCode<W> {
+        IntCode {
+            memory: Memory::with_max_size(program, max_size),
+            ip: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            halted: false,
+            trap_overflow: false,
+            tracer: None,
+            last_write: None,
+            decoded: Vec::new(),
+            cache_decoded: true,
+            self_mod: None,
+            profile: None,
+            deadline: None,
+            step_budget: None,
+        }
+    }
+
+    // Every run after `deadline` passes stops with `IntcodeError::LimitExceeded`.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    // Steps the machine may still take, across all runs, before stopping with
+    // `IntcodeError::LimitExceeded`.
+    pub fn set_step_limit(&mut self, steps: Option<u64>) {
+        self.step_budget = steps;
+    }
+
+    pub fn step_limit(&self) -> Option<u64> {
+        self.step_budget
+    }
+
+    // Off by default. While on, every completed instruction is counted by address and opcode.
+    // Counts carry on across `reset`; turning profiling off and on again clears them.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = if enabled { Some(Profile::new()) } else { None };
+    }
+
+    pub fn profile(&self) -> Option<&Profile<W>> {
+        self.profile.as_ref()
+    }
+
+    // Off by default. While on, writes to the words of instructions that have already executed
+    // are collected into a report. `reset` starts a fresh one.
+    pub fn track_self_modification(&mut self, enabled: bool) {
+        self.self_mod = if enabled {
+            Some(SelfModReport::new())
+        } else {
+            None
+        };
+    }
+
+    pub fn self_modification(&self) -> Option<&SelfModReport> {
+        self.self_mod.as_ref()
+    }
+
+    // On by default. Only addresses inside the dense region of memory are cached.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache_decoded = enabled;
+        self.decoded.clear();
+    }
+
+    pub fn decode_cache(&self) -> bool {
+        self.cache_decoded
+    }
+
+    // An instruction is at most four words, so a write can change any that starts up to three
+    // words before it.
+    fn invalidate(&mut self, address: usize) {
+        if address >= self.decoded.len() + 3 {
+            return;
+        }
+        let end = (address + 1).min(self.decoded.len());
+        for entry in &mut self.decoded[address.saturating_sub(3)..end] {
+            *entry = None;
+        }
+    }
+
+    // Add, Mul and relative base adjustments wrap by default. With the trap set they fail with
+    // `IntcodeError::ArithmeticOverflow` instead, so a result that comes back is exact.
+    pub fn set_trap_overflow(&mut self, trap: bool) {
+        self.trap_overflow = trap;
+    }
+
+    pub fn trap_overflow(&self) -> bool {
+        self.trap_overflow
+    }
+
+    pub fn patch(&mut self, patches: &[(isize, W)]) -> Result<(), IntcodeError> {
+        for (address, value) in patches {
+            self.poke(*address, *value)?;
+        }
+        Ok(())
+    }
+
+    fn checked_address(&self, address: isize) -> Result<usize, IntcodeError> {
+        let instruction = self.fetch(0);
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress {
+                ip: self.ip,
+                instruction,
+                address,
+            });
+        }
+        if address as usize >= self.memory.max_size() {
+            return Err(IntcodeError::MemoryLimit {
+                ip: self.ip,
+                instruction,
+                address: address as usize,
+            });
+        }
+        Ok(address as usize)
+    }
+
+    pub fn peek(&self, address: isize) -> Result<W, IntcodeError> {
+        let address = self.checked_address(address)?;
+        Ok(self.memory[address])
+    }
+
+    pub fn poke(&mut self, address: isize, value: W) -> Result<(), IntcodeError> {
+        let address = self.checked_address(address)?;
+        self.memory.set(address, value).unwrap();
+        self.invalidate(address);
+        Ok(())
+    }
+
+    pub fn memory(&self) -> &Memory<W> {
+        &self.memory
+    }
+
+    // Writes made through this bypass the decode cache, so it is dropped.
+    pub fn memory_mut(&mut self) -> &mut Memory<W> {
+        self.decoded.clear();
+        &mut self.memory
+    }
+
+    // Puts the machine back to its initial image and registers, dropping pending input. Only
+    // memory written since the last reset is restored. The tracer stays attached.
+    pub fn reset(&mut self) {
+        self.memory.reset();
+        self.decoded.clear();
+        if self.self_mod.is_some() {
+            self.self_mod = Some(SelfModReport::new());
+        }
+        self.ip = 0;
+        self.relative_base = 0;
+        self.input.clear();
+        self.halted = false;
+        self.last_write = None;
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    pub fn push_input(&mut self, value: W) {
+        self.input.push_back(value);
+    }
+
+    pub fn extend_input(&mut self, values: &[W]) {
+        self.input.extend(values);
+    }
+
+    // Input that has been supplied but not yet consumed.
+    pub fn input_queue(&self) -> &VecDeque<W> {
+        &self.input
+    }
+
+    pub fn input_queue_mut(&mut self) -> &mut VecDeque<W> {
+        &mut self.input
+    }
+
+    // Every instruction executed from now on is reported to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Box<dyn TraceSink<W> + Send>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn TraceSink<W> + Send>> {
+        self.tracer.take()
+    }
+
+    // The word at ip + offset, for error reports; words too wide for an isize saturate.
+    fn fetch(&self, offset: usize) -> isize {
+        let word = self.memory[self.ip + offset];
+        word.to_isize().unwrap_or(if word < W::ZERO {
+            isize::MIN
+        } else {
+            isize::MAX
+        })
+    }
+
+    pub fn decode(&self) -> Result<Instruction<W>, IntcodeError> {
+        if self.memory.get(self.ip).is_none() {
+            return Err(IntcodeError::IpOutOfRange { ip: self.ip });
+        }
+        Instruction::decode(self.ip, |address| self.memory[address])
+    }
+
+    fn address(&self, arg: &Arg<W>, instruction: isize) -> Result<usize, IntcodeError> {
+        let address = match *arg {
+            Arg::Immediate(_) => {
+                return Err(IntcodeError::ImmediateWrite {
+                    ip: self.ip,
+                    instruction,
+                })
+            }
+            Arg::Parameter(x) => x,
+            // An address that overflows an isize can't be in memory either way.
+            Arg::Relative(x) => {
+                x.checked_add(self.relative_base)
+                    .ok_or(IntcodeError::ArithmeticOverflow {
+                        ip: self.ip,
+                        instruction,
+                    })?
+            }
+        };
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress {
+                ip: self.ip,
+                instruction,
+                address,
+            });
+        }
+        Ok(address as usize)
+    }
+
+    fn read(&self, arg: &Arg<W>, instruction: isize) -> Result<W, IntcodeError> {
+        match *arg {
+            Arg::Immediate(x) => Ok(x),
+            _ => {
+                let address = self.address(arg, instruction)?;
+                self.memory.get(address).ok_or(IntcodeError::MemoryLimit {
+                    ip: self.ip,
+                    instruction,
+                    address,
+                })
+            }
+        }
+    }
+
+    fn write(&mut self, arg: &Arg<W>, instruction: isize, value: W) -> Result<(), IntcodeError> {
+        let address = self.address(arg, instruction)?;
+        self.last_write = Some((address, value));
+        self.invalidate(address);
+        if let Some(report) = self.self_mod.as_mut() {
+            report.write(self.ip, address);
+        }
+        self.memory
+            .set(address, value)
+            .ok_or(IntcodeError::MemoryLimit {
+                ip: self.ip,
+                instruction,
+                address,
+            })
+    }
+
+    fn jump(&mut self, target: isize, instruction: isize) -> Result<(), IntcodeError> {
+        if target < 0 || target as usize >= self.memory.max_size() {
+            return Err(IntcodeError::IpOverflow {
+                ip: self.ip,
+                instruction,
+            });
+        }
+        self.ip = target as usize;
+        Ok(())
+    }
+
+    fn jump_to(&mut self, target: W, instruction: isize) -> Result<(), IntcodeError> {
+        match target.to_isize() {
+            Some(target) => self.jump(target, instruction),
+            None => Err(IntcodeError::IpOverflow {
+                ip: self.ip,
+                instruction,
+            }),
+        }
+    }
+
+    fn arithmetic(
+        &self,
+        checked: Option<W>,
+        wrapped: W,
+        instruction: isize,
+    ) -> Result<W, IntcodeError> {
+        match (checked, self.trap_overflow) {
+            (Some(x), _) => Ok(x),
+            (None, false) => Ok(wrapped),
+            (None, true) => Err(IntcodeError::ArithmeticOverflow {
+                ip: self.ip,
+                instruction,
+            }),
+        }
+    }
+
+    fn adjust_relative_base(&mut self, offset: W, instruction: isize) -> Result<(), IntcodeError> {
+        let overflow = IntcodeError::ArithmeticOverflow {
+            ip: self.ip,
+            instruction,
+        };
+        let offset = offset.to_isize().ok_or(overflow)?;
+        let base = self.relative_base;
+        self.relative_base = match (base.checked_add(offset), self.trap_overflow) {
+            (Some(base), _) => base,
+            (None, false) => base.wrapping_add(offset),
+            (None, true) => return Err(overflow),
+        };
+        Ok(())
+    }
+
+    // Values read by each operand, or the resolved address for the operand written through.
+    fn operands(&self, instruction: &Instruction<W>, word: isize) -> Result<Vec<W>, IntcodeError> {
+        let write = instruction.write_arg();
+        let mut operands = Vec::new();
+        for (i, arg) in instruction.args().iter().enumerate() {
+            operands.push(if Some(i) == write {
+                W::from_isize(self.address(arg, word)? as isize)
+            } else {
+                self.read(arg, word)?
+            });
+        }
+        Ok(operands)
+    }
+
+    pub fn execute(&mut self, instruction: &Instruction<W>) -> Result<StepResult<W>, IntcodeError> {
+        if let Some(report) = self.self_mod.as_mut() {
+            report.executed(self.ip, instruction);
+        }
+        if self.profile.is_some() {
+            let ip = self.ip;
+            let result = self.execute_traced(instruction)?;
+            if result != StepResult::NeedInput {
+                self.profile.as_mut().unwrap().record(ip, instruction);
+            }
+            return Ok(result);
+        }
+        self.execute_traced(instruction)
+    }
+
+    fn execute_traced(
+        &mut self,
+        instruction: &Instruction<W>,
+    ) -> Result<StepResult<W>, IntcodeError> {
+        if self.tracer.is_none() {
+            return self.execute_untraced(instruction);
+        }
+        let ip = self.ip;
+        let operands = self.operands(instruction, self.fetch(0))?;
+        self.last_write = None;
+        let result = self.execute_untraced(instruction)?;
+        if result != StepResult::NeedInput {
+            let event = TraceEvent {
+                ip,
+                instruction: *instruction,
+                operands,
+                write: self.last_write.take(),
+            };
+            self.tracer.as_mut().unwrap().trace(&event);
+        }
+        Ok(result)
+    }
+
+    fn execute_untraced(
+        &mut self,
+        instruction: &Instruction<W>,
+    ) -> Result<StepResult<W>, IntcodeError> {
+        let word = self.fetch(0);
+        match instruction {
+            Instruction::Halt => {
+                self.halted = true;
+                return Ok(StepResult::Halted);
+            }
+            Instruction::Add(x, y, z) => {
+                let x = self.read(x, word)?;
+                let y = self.read(y, word)?;
+                let sum = self.arithmetic(x.checked_add(y), x.wrapping_add(y), word)?;
+                self.write(z, word, sum)?;
+            }
+            Instruction::Mul(x, y, z) => {
+                let x = self.read(x, word)?;
+                let y = self.read(y, word)?;
+                let product = self.arithmetic(x.checked_mul(y), x.wrapping_mul(y), word)?;
+                self.write(z, word, product)?;
+            }
+            Instruction::Input(x) => {
+                let value = match self.input.front() {
+                    Some(value) => *value,
+                    // Stall waiting for input.
+                    None => return Ok(StepResult::NeedInput),
+                };
+                self.write(x, word, value)?;
+                self.input.pop_front();
+            }
+            Instruction::Output(x) => {
+                let value = self.read(x, word)?;
+                self.jump((self.ip + 2) as isize, word)?;
+                return Ok(StepResult::Output(value));
+            }
+            Instruction::JNZ(x, y) => {
+                let x = self.read(x, word)?;
+                let y = self.read(y, word)?;
+                if x != W::ZERO {
+                    self.jump_to(y, word)?;
+                    return Ok(StepResult::Continue);
+                }
+            }
+            Instruction::JZ(x, y) => {
+                let x = self.read(x, word)?;
+                let y = self.read(y, word)?;
+                if x == W::ZERO {
+                    self.jump_to(y, word)?;
+                    return Ok(StepResult::Continue);
+                }
+            }
+            Instruction::LessThan(x, y, z) => {
+                let x = self.read(x, word)?;
+                let y = self.read(y, word)?;
+                self.write(z, word, if x < y { W::ONE } else { W::ZERO })?;
+            }
+            Instruction::Equals(x, y, z) => {
+                let x = self.read(x, word)?;
+                let y = self.read(y, word)?;
+                self.write(z, word, if x == y { W::ONE } else { W::ZERO })?;
+            }
+            Instruction::AdjustRelativeBase(x) => {
+                let offset = self.read(x, word)?;
+                self.adjust_relative_base(offset, word)?;
+            }
+        }
+        let next = (self.ip + instruction.size()) as isize;
+        self.jump(next, word)?;
+        Ok(StepResult::Continue)
+    }
+
+    pub fn step(&mut self) -> Result<StepResult<W>, IntcodeError> {
+        if self.halted {
+            return Ok(StepResult::Halted);
+        }
+        let instruction = self.decode_cached()?;
+        self.execute(&instruction)
+    }
+
+    fn decode_cached(&mut self) -> Result<Instruction<W>, IntcodeError> {
+        if let Some(Some(instruction)) = self.decoded.get(self.ip) {
+            return Ok(*instruction);
+        }
+        let instruction = self.decode()?;
+        if self.cache_decoded && self.ip < self.memory.as_slice().len() {
+            if self.decoded.len() <= self.ip {
+                self.decoded.resize(self.memory.as_slice().len(), None);
+            }
+            self.decoded[self.ip] = Some(instruction);
+        }
+        Ok(instruction)
+    }
+
+    // Runs until the program halts, blocks on input or produces output.
+    pub fn run(&mut self) -> Result<RunState<W>, IntcodeError> {
+        self.run_bounded(None)
+    }
+
+    // As `run`, but fails with `IntcodeError::LimitExceeded` rather than take more than
+    // `max_steps` steps.
+    pub fn run_with_limit(&mut self, max_steps: u64) -> Result<RunState<W>, IntcodeError> {
+        self.run_bounded(Some(max_steps))
+    }
+
+    // Only instructions that run count as steps; finding the machine halted or blocked on input
+    // costs nothing.
+    fn run_bounded(&mut self, max_steps: Option<u64>) -> Result<RunState<W>, IntcodeError> {
+        let mut steps = 0;
+        loop {
+            if self.halted {
+                return Ok(RunState::Halted);
+            }
+            let expired = match self.deadline {
+                Some(deadline) => steps % DEADLINE_INTERVAL == 0 && Instant::now() >= deadline,
+                None => false,
+            };
+            if expired || Some(steps) == max_steps || self.step_budget == Some(0) {
+                if self.input.is_empty() && matches!(self.decode(), Ok(Instruction::Input(_))) {
+                    return Ok(RunState::NeedInput);
+                }
+                return Err(IntcodeError::LimitExceeded { ip: self.ip });
+            }
+            let result = self.step()?;
+            if result != StepResult::NeedInput {
+                steps += 1;
+                if let Some(budget) = &mut self.step_budget {
+                    *budget -= 1;
+                }
+            }
+            match result {
+                StepResult::Continue => {}
+                StepResult::Halted => return Ok(RunState::Halted),
+                StepResult::NeedInput => return Ok(RunState::NeedInput),
+                StepResult::Output(x) => return Ok(RunState::Output(x)),
+            }
+        }
+    }
+
+    // Runs until the program halts or `io` has no input to give, sending every output to `io`.
+    pub fn run_io<T: IntcodeIo<W> + ?Sized>(
+        &mut self,
+        io: &mut T,
+    ) -> Result<RunState<W>, IntcodeError> {
+        loop {
+            match self.run()? {
+                RunState::Halted => return Ok(RunState::Halted),
+                RunState::Output(x) => io.write(x),
+                RunState::NeedInput => match io.read() {
+                    Some(x) => self.push_input(x),
+                    None => return Ok(RunState::NeedInput),
+                },
+            }
+        }
+    }
+
+    // Runs until the program halts, collecting every output. Blocking on input is an error.
+    pub fn run_to_halt(&mut self) -> Result<Vec<W>, IntcodeError> {
+        let mut output = Vec::new();
+        while let Some(x) = self.run_until_output()? {
+            output.push(x);
+        }
+        Ok(output)
+    }
+
+    // Returns the next output, or None once the program halts. Blocking on input is an error.
+    pub fn run_until_output(&mut self) -> Result<Option<W>, IntcodeError> {
+        match self.run()? {
+            RunState::Halted => Ok(None),
+            RunState::Output(x) => Ok(Some(x)),
+            RunState::NeedInput => Err(IntcodeError::InputExhausted {
+                ip: self.ip,
+                instruction: self.fetch(0),
+            }),
+        }
+    }
+}
+
+#[test]
+fn test_relative_base() {
+    let quine = fixtures::quine();
+    let mut machine = IntCode::new(&quine);
+    assert_eq!(machine.run_to_halt().unwrap(), quine);
+    assert_eq!(machine.relative_base(), 16);
+
+    let mut machine = IntCode::new(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![1219070632396864]);
+
+    // Relative-mode output store: in [r+5] then echo it back.
+    let mut machine = IntCode::new(&[109, 3, 203, 5, 204, 5, 99, 0, 0]);
+    machine.push_input(42);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![42]);
+    assert_eq!(machine.memory()[8], 42);
+}
+
+#[test]
+fn test_errors() {
+    for trap in &[false, true] {
+        let mut machine = IntCode::new(&[109, isize::MAX, 204, 1, 99]);
+        machine.set_trap_overflow(*trap);
+        assert_eq!(
+            machine.run_to_halt(),
+            Err(IntcodeError::ArithmeticOverflow {
+                ip: 2,
+                instruction: 204
+            })
+        );
+    }
+    assert_eq!(
+        IntCode::new(&[1, 0, 0, 0, 42]).run_to_halt(),
+        Err(IntcodeError::UnknownOpcode {
+            ip: 4,
+            instruction: 42
+        })
+    );
+    assert_eq!(
+        IntCode::new(&[301, 0, 0, 0, 99]).run_to_halt(),
+        Err(IntcodeError::InvalidMode {
+            ip: 0,
+            instruction: 301,
+            mode: 3
+        })
+    );
+    assert_eq!(
+        IntCode::new(&[11101, 1, 1, 0, 99]).run_to_halt(),
+        Err(IntcodeError::ImmediateWrite {
+            ip: 0,
+            instruction: 11101
+        })
+    );
+    assert_eq!(
+        IntCode::new(&[4, -3, 99]).run_to_halt(),
+        Err(IntcodeError::NegativeAddress {
+            ip: 0,
+            instruction: 4,
+            address: -3
+        })
+    );
+    assert_eq!(
+        IntCode::new(&[1105, 1, -1]).run_to_halt(),
+        Err(IntcodeError::IpOverflow {
+            ip: 0,
+            instruction: 1105
+        })
+    );
+    let snapshot = "intcode-snapshot 1\nip 5\nrelative_base 0\nhalted 0\ntrap_overflow 0\n\
+                    max_size 3\ninput \nimage 3\n104,7,99\nmemory 3\n104,7,99\nsparse 0\n";
+    assert_eq!(
+        IntCode::load(snapshot.as_bytes()).unwrap().run(),
+        Err(IntcodeError::IpOutOfRange { ip: 5 })
+    );
+}
+
+#[test]
+fn test_word_types() {
+    let square = |x: isize| vec![1102, x, x, 7, 4, 7, 99, 0];
+
+    let mut machine = IntCode::new(&square(34915192));
+    machine.set_trap_overflow(true);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![1219070632396864]);
+
+    let program: Vec<i32> = square(34915192).iter().map(|x| *x as i32).collect();
+    let mut machine = IntCode::from_words(&program, DEFAULT_MAX_SIZE);
+    assert_eq!(
+        machine.run_to_halt().unwrap(),
+        vec![1219070632396864isize as i32]
+    );
+    machine.reset();
+    machine.set_trap_overflow(true);
+    assert_eq!(
+        machine.run_to_halt(),
+        Err(IntcodeError::ArithmeticOverflow {
+            ip: 0,
+            instruction: 1102
+        })
+    );
+
+    // Squaring 3037000500 overflows 64 bits but not 128.
+    let mut machine = IntCode::new(&square(3037000500));
+    machine.set_trap_overflow(true);
+    assert!(machine.run_to_halt().is_err());
+    let program: Vec<i128> = square(3037000500).iter().map(|x| *x as i128).collect();
+    let mut machine = IntCode::from_words(&program, DEFAULT_MAX_SIZE);
+    machine.set_trap_overflow(true);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![9223372037000250000]);
+
+    // Immediates can use the whole word, but addresses still have to fit an isize.
+    let program = [1101i128, 1 << 70, 1, 7, 4, 7, 99, 0];
+    let mut machine = IntCode::from_words(&program, DEFAULT_MAX_SIZE);
+    let mut output = Vec::new();
+    let mut io = FnIo::new(|| None, |x| output.push(x));
+    assert_eq!(machine.run_io(&mut io), Ok(RunState::Halted));
+    assert_eq!(output, vec![(1 << 70) + 1]);
+    let mut machine = IntCode::from_words(&[4, 1i128 << 70, 99], DEFAULT_MAX_SIZE);
+    assert_eq!(
+        machine.run_to_halt(),
+        Err(IntcodeError::WordRange { ip: 0, address: 1 })
+    );
+}
+
+#[test]
+fn test_decode_cache() {
+    let program = fixtures::self_modifying();
+    for cached in &[true, false] {
+        let mut machine = IntCode::new(&program);
+        machine.set_decode_cache(*cached);
+        assert_eq!(machine.run_to_halt().unwrap(), vec![0, 1, 2]);
+        machine.reset();
+        assert_eq!(machine.run_to_halt().unwrap(), vec![0, 1, 2]);
+    }
+
+    // Writes from outside the machine are seen too.
+    let mut machine = IntCode::new(&[104, 1, 1105, 1, 0]);
+    assert_eq!(machine.run(), Ok(RunState::Output(1)));
+    machine.poke(1, 2).unwrap();
+    assert_eq!(machine.run(), Ok(RunState::Output(2)));
+    machine.memory_mut().set(1, 3).unwrap();
+    assert_eq!(machine.run(), Ok(RunState::Output(3)));
+}
+
+#[test]
+fn test_limits() {
+    use std::time::Duration;
+
+    // Loops forever.
+    let program = [1101, 0, 0, 7, 1105, 1, 0, 0];
+    let mut machine = IntCode::new(&program);
+    assert_eq!(
+        machine.run_with_limit(5),
+        Err(IntcodeError::LimitExceeded { ip: 4 })
+    );
+    assert_eq!(
+        machine.run_with_limit(5),
+        Err(IntcodeError::LimitExceeded { ip: 0 })
+    );
+    let mut machine = IntCode::new(&[104, 1, 99]);
+    assert_eq!(machine.run_with_limit(1), Ok(RunState::Output(1)));
+    assert_eq!(machine.run_with_limit(1), Ok(RunState::Halted));
+
+    let mut machine = IntCode::new(&program);
+    machine.set_deadline(Some(Instant::now() + Duration::from_millis(20)));
+    assert!(matches!(
+        machine.run_to_halt(),
+        Err(IntcodeError::LimitExceeded { .. })
+    ));
+    let mut io = QueueIo::new(&[]);
+    assert!(machine.run_io(&mut io).is_err());
+
+    // The step limit is shared by every run, so output doesn't renew it.
+    let mut machine = IntCode::new(&[104, 1, 1105, 1, 0]);
+    machine.set_step_limit(Some(100));
+    assert_eq!(
+        machine.run_to_halt(),
+        Err(IntcodeError::LimitExceeded { ip: 0 })
+    );
+    assert_eq!(machine.step_limit(), Some(0));
+    assert!(machine.run_with_limit(10).is_err());
+    machine.set_step_limit(Some(1));
+    assert_eq!(machine.run(), Ok(RunState::Output(1)));
+
+    // Waiting for input and sitting halted don't use up steps.
+    let mut machine = IntCode::new(&[3, 0, 4, 0, 99]);
+    machine.set_step_limit(Some(3));
+    for _ in 0..3 {
+        assert_eq!(machine.run(), Ok(RunState::NeedInput));
+    }
+    assert_eq!(machine.step_limit(), Some(3));
+    machine.push_input(5);
+    assert_eq!(machine.run(), Ok(RunState::Output(5)));
+    assert_eq!(machine.run(), Ok(RunState::Halted));
+    assert_eq!(machine.step_limit(), Some(0));
+    assert_eq!(machine.run_with_limit(0), Ok(RunState::Halted));
+    let mut machine = IntCode::new(&[3, 0, 99]);
+    machine.set_step_limit(Some(0));
+    assert_eq!(machine.run(), Ok(RunState::NeedInput));
+}
+
+#[test]
+fn test_memory_limit() {
+    let mut machine = IntCode::new(&[1101, 2, 3, 1_000_000, 4, 1_000_000, 99]);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![5]);
+    assert_eq!(machine.memory()[1_000_000], 5);
+    assert_eq!(
+        IntCode::with_memory_limit(&[1101, 2, 3, 100, 99], 64).run_to_halt(),
+        Err(IntcodeError::MemoryLimit {
+            ip: 0,
+            instruction: 1101,
+            address: 100
+        })
+    );
+}
+
+#[test]
+fn test_run_states() {
+    // Echo two inputs then halt.
+    let mut machine = IntCode::new(&[3, 9, 4, 9, 3, 9, 4, 9, 99, 0]);
+    assert_eq!(machine.run(), Ok(RunState::NeedInput));
+    machine.push_input(7);
+    assert_eq!(machine.run(), Ok(RunState::Output(7)));
+    assert_eq!(machine.run(), Ok(RunState::NeedInput));
+    assert_eq!(
+        machine.run_until_output(),
+        Err(IntcodeError::InputExhausted {
+            ip: 4,
+            instruction: 3
+        })
+    );
+    machine.push_input(8);
+    assert_eq!(machine.run_until_output(), Ok(Some(8)));
+    assert_eq!(machine.run(), Ok(RunState::Halted));
+    assert_eq!(machine.run_until_output(), Ok(None));
+}
+
+#[test]
+fn test_io() {
+    let program = fixtures::doubler();
+
+    let mut io = QueueIo::new(&[1, 2, 3]);
+    let mut machine = IntCode::new(&program);
+    assert_eq!(machine.run_io(&mut io), Ok(RunState::NeedInput));
+    assert_eq!(io.output, vec![2, 4, 6]);
+    io.input.push_back(0);
+    assert_eq!(machine.run_io(&mut io), Ok(RunState::Halted));
+
+    let mut inputs = vec![0, 5];
+    let mut outputs = Vec::new();
+    let mut io = FnIo::new(|| inputs.pop(), |x| outputs.push(x));
+    assert_eq!(IntCode::new(&program).run_io(&mut io), Ok(RunState::Halted));
+    assert_eq!(outputs, vec![10]);
+
+    let (in_tx, in_rx) = std::sync::mpsc::channel();
+    let (out_tx, out_rx) = std::sync::mpsc::channel();
+    let handle = std::thread::spawn(move || {
+        let mut io = ChannelIo::new(in_rx, out_tx);
+        IntCode::new(&program).run_io(&mut io)
+    });
+    in_tx.send(21).unwrap();
+    assert_eq!(out_rx.recv(), Ok(42));
+    in_tx.send(0).unwrap();
+    assert_eq!(handle.join().unwrap(), Ok(RunState::Halted));
+}
+
+#[test]
+fn test_patches() {
+    let program = [1, 0, 0, 0, 99];
+    let mut machine = IntCode::with_patches(&program, &[(1, 4), (2, 4)]).unwrap();
+    machine.run_to_halt().unwrap();
+    assert_eq!(machine.peek(0), Ok(198));
+    machine.reset();
+    assert_eq!(machine.peek(1), Ok(0));
+
+    assert_eq!(machine.poke(10_000_000, 1), Ok(()));
+    assert_eq!(machine.peek(10_000_000), Ok(1));
+    assert_eq!(
+        machine.peek(-1),
+        Err(IntcodeError::NegativeAddress {
+            ip: 0,
+            instruction: 1,
+            address: -1
+        })
+    );
+    assert_eq!(
+        IntCode::with_memory_limit(&program, 8).poke(8, 1),
+        Err(IntcodeError::MemoryLimit {
+            ip: 0,
+            instruction: 1,
+            address: 8
+        })
+    );
+    assert!(IntCode::with_patches(&program, &[(-5, 0)]).is_err());
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::IntcodeIo;
+
+// A run of output: printable text, or a value outside the ASCII range passed through as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chunk {
+    Text(String),
+    Raw(isize),
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chunk::Text(text) => write!(f, "{}", text),
+            Chunk::Raw(x) => writeln!(f, "{}", x),
+        }
+    }
+}
+
+// Character codes for `line`, with a trailing newline added if it doesn't have one. None if
+// the line isn't ASCII.
+pub fn encode(line: &str) -> Option<Vec<isize>> {
+    if !line.is_ascii() {
+        return None;
+    }
+    let mut values: Vec<isize> = line.bytes().map(isize::from).collect();
+    if !line.ends_with('\n') {
+        values.push(isize::from(b'\n'));
+    }
+    Some(values)
+}
+
+// Consecutive ASCII values are merged into one text chunk.
+pub fn decode(values: &[isize]) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    for &x in values {
+        push_value(&mut chunks, x);
+    }
+    chunks
+}
+
+fn push_value(chunks: &mut Vec<Chunk>, x: isize) {
+    if (0..128).contains(&x) {
+        if let Some(Chunk::Text(text)) = chunks.last_mut() {
+            text.push(x as u8 as char);
+            return;
+        }
+        chunks.push(Chunk::Text((x as u8 as char).to_string()));
+    } else {
+        chunks.push(Chunk::Raw(x));
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AsciiIo {
+    pub input: VecDeque<isize>,
+    pub output: Vec<Chunk>,
+}
+
+impl AsciiIo {
+    pub fn new() -> AsciiIo {
+        AsciiIo::default()
+    }
+
+    // False, queueing nothing, if the line isn't ASCII.
+    pub fn push_line(&mut self, line: &str) -> bool {
+        match encode(line) {
+            Some(values) => {
+                self.input.extend(values);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Output decoded since the last call.
+    pub fn take_output(&mut self) -> Vec<Chunk> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl IntcodeIo for AsciiIo {
+    fn read(&mut self) -> Option<isize> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: isize) {
+        push_value(&mut self.output, value);
+    }
+}
+
+#[test]
+fn test_ascii() {
+    use super::{IntCode, RunState};
+
+    assert_eq!(encode("hi"), Some(vec![104, 105, 10]));
+    assert_eq!(encode("hi\n"), Some(vec![104, 105, 10]));
+    assert_eq!(encode("é"), None);
+    assert_eq!(
+        decode(&[104, 105, 10, 1000, -1, 111, 107]),
+        vec![
+            Chunk::Text("hi\n".to_string()),
+            Chunk::Raw(1000),
+            Chunk::Raw(-1),
+            Chunk::Text("ok".to_string()),
+        ]
+    );
+
+    // Echoes a line, then reports its length as a raw value.
+    let program = super::asm::assemble(
+        "
+        loop:   IN [c]
+                OUT [c]
+                ADD [n], #1000, [n]
+                EQ [c], #10, [done]
+                JZ [done], #loop
+                OUT [n]
+                HLT
+        c:      DATA 0
+        n:      DATA 0
+        done:   DATA 0
+        ",
+    )
+    .unwrap();
+    let mut machine = IntCode::new(&program);
+    let mut io = AsciiIo::new();
+    assert_eq!(machine.run_io(&mut io), Ok(RunState::NeedInput));
+    assert!(io.push_line("abc"));
+    assert_eq!(machine.run_io(&mut io), Ok(RunState::Halted));
+    let output = io.take_output();
+    assert_eq!(
+        output,
+        vec![Chunk::Text("abc\n".to_string()), Chunk::Raw(4000)]
+    );
+    let text: String = output.iter().map(|x| x.to_string()).collect();
+    assert_eq!(text, "abc\n4000\n");
+    assert!(io.take_output().is_empty());
+}
@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{Arg, Instruction};
+
+// Assembly syntax, one statement per line:
+//
+//     ; comment
+//     const LIMIT = 10
+//     loop:   ADD [counter], #1, [counter]
+//             LT [counter], #LIMIT, [r+1]
+//             JNZ [r+1], #loop
+//             HLT
+//     counter: DATA 0
+//
+// Operands are `#x` (immediate), `[x]` (position) or `[r+x]` (relative), where `x` is a sum of
+// integers, labels and constants. A numeric label such as `0012:` asserts the current address,
+// so `disasm::listing` output reassembles to the original program.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(isize),
+    Symbol(String),
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    // (negated, term, column)
+    terms: Vec<(bool, Term, usize)>,
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Immediate(Expr),
+    Position(Expr),
+    Relative(Expr),
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Data(Vec<Expr>),
+}
+
+struct Statement {
+    line: usize,
+    item: Item,
+}
+
+fn arity(mnemonic: &str) -> Option<usize> {
+    Some(match mnemonic {
+        "HLT" => 0,
+        "IN" | "OUT" | "ARB" => 1,
+        "JNZ" | "JZ" => 2,
+        "ADD" | "MUL" | "LT" | "EQ" => 3,
+        _ => return None,
+    })
+}
+
+fn build(mnemonic: &str, a: &[Arg]) -> Instruction {
+    match mnemonic {
+        "HLT" => Instruction::Halt,
+        "ADD" => Instruction::Add(a[0], a[1], a[2]),
+        "MUL" => Instruction::Mul(a[0], a[1], a[2]),
+        "IN" => Instruction::Input(a[0]),
+        "OUT" => Instruction::Output(a[0]),
+        "JNZ" => Instruction::JNZ(a[0], a[1]),
+        "JZ" => Instruction::JZ(a[0], a[1]),
+        "LT" => Instruction::LessThan(a[0], a[1], a[2]),
+        "EQ" => Instruction::Equals(a[0], a[1], a[2]),
+        "ARB" => Instruction::AdjustRelativeBase(a[0]),
+        _ => unreachable!(),
+    }
+}
+
+struct Cursor {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Cursor {
+    fn new(text: &str, line: usize) -> Cursor {
+        Cursor {
+            chars: text.chars().collect(),
+            pos: 0,
+            line,
+        }
+    }
+
+    fn column(&self) -> usize {
+        self.pos + 1
+    }
+
+    fn error<T>(&self, column: usize, message: String) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.line,
+            column,
+            message,
+        })
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    // The next non-blank character, treating a comment as the end of the line.
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        match self.chars.get(self.pos) {
+            Some(';') | None => None,
+            Some(c) => Some(*c),
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), AsmError> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        let found = self.describe_next();
+        self.error(self.column(), format!("expected '{}', found {}", c, found))
+    }
+
+    fn describe_next(&mut self) -> String {
+        match self.peek() {
+            Some(c) => format!("'{}'", c),
+            None => "end of line".to_string(),
+        }
+    }
+
+    fn word(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.pos < self.chars.len()
+            && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_')
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            None
+        } else {
+            Some(self.chars[start..self.pos].iter().collect())
+        }
+    }
+
+    fn term(&mut self) -> Result<(Term, usize), AsmError> {
+        self.skip_ws();
+        let column = self.column();
+        let word = match self.word() {
+            Some(word) => word,
+            None => {
+                let found = self.describe_next();
+                return self.error(column, format!("expected a value, found {}", found));
+            }
+        };
+        if word.chars().next().unwrap().is_ascii_digit() {
+            match word.parse() {
+                Ok(x) => Ok((Term::Number(x), column)),
+                Err(_) => self.error(column, format!("invalid number '{}'", word)),
+            }
+        } else {
+            Ok((Term::Symbol(word), column))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmError> {
+        let mut negated = if self.eat('-') {
+            true
+        } else {
+            self.eat('+');
+            false
+        };
+        let mut terms = Vec::new();
+        loop {
+            let (term, column) = self.term()?;
+            terms.push((negated, term, column));
+            if self.eat('+') {
+                negated = false;
+            } else if self.eat('-') {
+                negated = true;
+            } else {
+                return Ok(Expr { terms });
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        if self.eat('#') {
+            return Ok(Operand::Immediate(self.expr()?));
+        }
+        self.expect('[')?;
+        let start = self.pos;
+        self.skip_ws();
+        if self.word().as_deref() == Some("r") {
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Operand::Relative(Expr {
+                        terms: vec![(false, Term::Number(0), self.column())],
+                    }));
+                }
+                Some('+') | Some('-') => {
+                    let expr = self.expr()?;
+                    self.expect(']')?;
+                    return Ok(Operand::Relative(expr));
+                }
+                _ => {}
+            }
+        }
+        self.pos = start;
+        let expr = self.expr()?;
+        self.expect(']')?;
+        Ok(Operand::Position(expr))
+    }
+
+    fn list<T, F>(&mut self, mut parse: F) -> Result<Vec<T>, AsmError>
+    where
+        F: FnMut(&mut Self) -> Result<T, AsmError>,
+    {
+        let mut items = Vec::new();
+        if self.peek().is_none() {
+            return Ok(items);
+        }
+        loop {
+            items.push(parse(self)?);
+            if !self.eat(',') {
+                return Ok(items);
+            }
+        }
+    }
+
+    fn end(&mut self) -> Result<(), AsmError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(c) => self.error(self.column(), format!("unexpected '{}'", c)),
+        }
+    }
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, isize>,
+    constants: HashMap<String, Expr>,
+    address: usize,
+}
+
+impl Assembler {
+    fn define(&mut self, cursor: &Cursor, column: usize, name: &str) -> Result<(), AsmError> {
+        if name == "r" {
+            return cursor.error(column, "'r' is reserved for relative operands".to_string());
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return cursor.error(column, format!("'{}' is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: usize, text: &str) -> Result<(), AsmError> {
+        let mut cursor = Cursor::new(text, line);
+        loop {
+            if cursor.peek().is_none() {
+                return Ok(());
+            }
+            let column = cursor.column();
+            let start = cursor.pos;
+            let word = match cursor.word() {
+                Some(word) => word,
+                None => {
+                    let found = cursor.describe_next();
+                    return cursor.error(column, format!("expected a statement, found {}", found));
+                }
+            };
+            if !cursor.eat(':') {
+                cursor.pos = start;
+                break;
+            }
+            if word.chars().all(|c| c.is_ascii_digit()) {
+                let address: usize = match word.parse() {
+                    Ok(x) => x,
+                    Err(_) => return cursor.error(column, format!("invalid address '{}'", word)),
+                };
+                if address != self.address {
+                    return cursor.error(
+                        column,
+                        format!(
+                            "address {} does not match position {}",
+                            address, self.address
+                        ),
+                    );
+                }
+            } else {
+                self.define(&cursor, column, &word)?;
+                self.labels.insert(word, self.address as isize);
+            }
+        }
+
+        let column = cursor.column();
+        let word = cursor.word().unwrap();
+        let keyword = word.to_uppercase();
+        let item = match keyword.as_str() {
+            "CONST" => {
+                cursor.skip_ws();
+                let name_column = cursor.column();
+                let name = match cursor.word() {
+                    Some(name) => name,
+                    None => {
+                        return cursor.error(name_column, "expected a constant name".to_string())
+                    }
+                };
+                self.define(&cursor, name_column, &name)?;
+                cursor.expect('=')?;
+                let expr = cursor.expr()?;
+                cursor.end()?;
+                self.constants.insert(name, expr);
+                return Ok(());
+            }
+            "DATA" => {
+                let values = cursor.list(|c| c.expr())?;
+                if values.is_empty() {
+                    return cursor
+                        .error(cursor.column(), "DATA needs at least one value".to_string());
+                }
+                self.address += values.len();
+                Item::Data(values)
+            }
+            _ => {
+                let expected = match arity(&keyword) {
+                    Some(n) => n,
+                    None => return cursor.error(column, format!("unknown mnemonic '{}'", word)),
+                };
+                let operands = cursor.list(|c| {
+                    c.skip_ws();
+                    let column = c.column();
+                    c.operand().map(|operand| (column, operand))
+                })?;
+                if operands.len() != expected {
+                    return cursor.error(
+                        column,
+                        format!(
+                            "{} takes {} operand(s), found {}",
+                            keyword,
+                            expected,
+                            operands.len()
+                        ),
+                    );
+                }
+                // Only the modes matter for finding the operand written through.
+                let modes: Vec<Arg> = operands
+                    .iter()
+                    .map(|(_, operand)| match operand {
+                        Operand::Immediate(_) => Arg::Immediate(0),
+                        Operand::Position(_) => Arg::Parameter(0),
+                        Operand::Relative(_) => Arg::Relative(0),
+                    })
+                    .collect();
+                if let Some(i) = build(&keyword, &modes).write_arg() {
+                    if let (column, Operand::Immediate(_)) = operands[i] {
+                        return cursor.error(
+                            column,
+                            format!("{} can't write to an immediate operand", keyword),
+                        );
+                    }
+                }
+                self.address += 1 + expected;
+                Item::Instruction {
+                    mnemonic: keyword,
+                    operands: operands.into_iter().map(|x| x.1).collect(),
+                }
+            }
+        };
+        cursor.end()?;
+        self.statements.push(Statement { line, item });
+        Ok(())
+    }
+
+    fn eval(&self, line: usize, expr: &Expr, depth: usize) -> Result<isize, AsmError> {
+        let mut total = 0isize;
+        for (negated, term, column) in &expr.terms {
+            let value = match term {
+                Term::Number(x) => *x,
+                Term::Symbol(name) => {
+                    if let Some(x) = self.labels.get(name) {
+                        *x
+                    } else if let Some(e) = self.constants.get(name) {
+                        if depth > 64 {
+                            return Err(AsmError {
+                                line,
+                                column: *column,
+                                message: format!(
+                                    "constant '{}' is defined in terms of itself",
+                                    name
+                                ),
+                            });
+                        }
+                        self.eval(line, e, depth + 1)?
+                    } else {
+                        return Err(AsmError {
+                            line,
+                            column: *column,
+                            message: format!("undefined symbol '{}'", name),
+                        });
+                    }
+                }
+            };
+            total = if *negated {
+                total.checked_sub(value)
+            } else {
+                total.checked_add(value)
+            }
+            .ok_or(AsmError {
+                line,
+                column: *column,
+                message: "value overflows".to_string(),
+            })?;
+        }
+        Ok(total)
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<isize>, AsmError> {
+    let mut asm = Assembler {
+        statements: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        address: 0,
+    };
+    for (i, text) in source.lines().enumerate() {
+        asm.line(i + 1, text)?;
+    }
+
+    let mut program = Vec::with_capacity(asm.address);
+    for statement in &asm.statements {
+        let line = statement.line;
+        match &statement.item {
+            Item::Data(values) => {
+                for value in values {
+                    program.push(asm.eval(line, value, 0)?);
+                }
+            }
+            Item::Instruction { mnemonic, operands } => {
+                let mut args = Vec::new();
+                for operand in operands {
+                    args.push(match operand {
+                        Operand::Immediate(e) => Arg::Immediate(asm.eval(line, e, 0)?),
+                        Operand::Position(e) => Arg::Parameter(asm.eval(line, e, 0)?),
+                        Operand::Relative(e) => Arg::Relative(asm.eval(line, e, 0)?),
+                    });
+                }
+                program.extend(build(mnemonic, &args).encode());
+            }
+        }
+    }
+    Ok(program)
+}
+
+#[test]
+fn test_assemble() {
+    let source = "
+        ; Counts down from LIMIT, printing each value.
+        const LIMIT = 3
+        start:  ADD #LIMIT, #0, [counter]
+        loop:   OUT [counter]
+                ADD [counter], #-1, [counter]
+                JNZ [counter], #loop
+                HLT
+        counter: data 0
+    ";
+    let program = assemble(source).unwrap();
+    assert_eq!(
+        program,
+        vec![1101, 3, 0, 14, 4, 14, 1001, 14, -1, 14, 1005, 14, 4, 99, 0]
+    );
+    let mut machine = super::IntCode::new(&program);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![3, 2, 1]);
+
+    assert_eq!(
+        assemble("ARB #1\nOUT [r-1]\nIN [r]\nEQ [buf+1], #2, [r+3]\nbuf: DATA 5, 6").unwrap(),
+        vec![109, 1, 204, -1, 203, 0, 21008, 11, 2, 3, 5, 6]
+    );
+
+    assert_eq!(
+        assemble("HLT\n  ADD #1, #2\n"),
+        Err(AsmError {
+            line: 2,
+            column: 3,
+            message: "ADD takes 3 operand(s), found 2".to_string()
+        })
+    );
+    assert_eq!(
+        assemble("JNZ #1, #nowhere").unwrap_err().to_string(),
+        "1:10: undefined symbol 'nowhere'"
+    );
+    assert_eq!(
+        assemble("ADD #1, #2, #3").unwrap_err().to_string(),
+        "1:13: ADD can't write to an immediate operand"
+    );
+    assert_eq!(
+        assemble("OUT [3").unwrap_err().to_string(),
+        "1:7: expected ']', found end of line"
+    );
+
+    // Disassembler listings reassemble to the original program.
+    let program = [
+        3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8, 1105, 1, 5, 1, 0, 4, 3, 99, 7,
+    ];
+    let listing = super::disasm::listing(&program);
+    assert_eq!(assemble(&listing).unwrap(), program.to_vec());
+}
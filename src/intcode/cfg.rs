@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::disasm;
+use super::{Arg, Instruction};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    // Control continues to the next instruction in memory.
+    Fall(usize),
+    // An immediate jump target.
+    Jump(usize),
+}
+
+impl Edge {
+    pub fn target(&self) -> usize {
+        match *self {
+            Edge::Fall(x) | Edge::Jump(x) => x,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub successors: Vec<Edge>,
+    // Ends in a jump whose target is only known at run time.
+    pub indirect: bool,
+}
+
+impl BasicBlock {
+    // Address one past the last word of the block.
+    pub fn end(&self) -> usize {
+        let (address, instruction) = self.instructions.last().unwrap();
+        address + instruction.size()
+    }
+}
+
+fn is_jump(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::JNZ(_, _) | Instruction::JZ(_, _))
+}
+
+fn is_indirect(instruction: &Instruction) -> bool {
+    match *instruction {
+        // Conditions that can never hold.
+        Instruction::JNZ(Arg::Immediate(0), _) => false,
+        Instruction::JZ(Arg::Immediate(x), _) if x != 0 => false,
+        Instruction::JNZ(_, y) | Instruction::JZ(_, y) => !matches!(y, Arg::Immediate(_)),
+        _ => false,
+    }
+}
+
+// Basic blocks of a program, keyed by start address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    blocks: BTreeMap<usize, BasicBlock>,
+}
+
+impl Cfg {
+    // The code statically reachable from address 0. Indirect jumps are marked but not followed.
+    pub fn new(program: &[isize]) -> Cfg {
+        Cfg::build(program, false)
+    }
+
+    // Also explores the address after every jump, which is where computed jumps usually
+    // return to. The extra blocks may well be data that never runs.
+    pub fn speculative(program: &[isize]) -> Cfg {
+        Cfg::build(program, true)
+    }
+
+    fn build(program: &[isize], speculative: bool) -> Cfg {
+        let mut code = BTreeMap::new();
+        let mut work = vec![0];
+        while let Some(address) = work.pop() {
+            if code.contains_key(&address) {
+                continue;
+            }
+            if let Some(instruction) = disasm::decode_exact(program, address) {
+                code.insert(address, instruction);
+                work.extend(instruction.successors(address));
+                if speculative && (is_jump(&instruction) || instruction == Instruction::Halt) {
+                    work.push(address + instruction.size());
+                }
+            }
+        }
+
+        // A block starts at 0, at a jump target, after a jump or halt, and wherever the
+        // previous instruction doesn't fall through into it.
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (address, instruction) in &code {
+            if is_jump(instruction) || *instruction == Instruction::Halt {
+                leaders.insert(address + instruction.size());
+                leaders.extend(instruction.successors(*address));
+            }
+        }
+
+        let mut blocks: BTreeMap<usize, BasicBlock> = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (address, instruction) in code {
+            if let Some(block) = current.take() {
+                if block.end() == address && !leaders.contains(&address) {
+                    current = Some(block);
+                } else {
+                    blocks.insert(block.start, block);
+                }
+            }
+            let block = current.get_or_insert_with(|| BasicBlock {
+                start: address,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+                indirect: false,
+            });
+            block.instructions.push((address, instruction));
+            let next = address + instruction.size();
+            if is_jump(&instruction) || instruction == Instruction::Halt {
+                block.successors = instruction
+                    .successors(address)
+                    .into_iter()
+                    .map(|x| {
+                        if x == next {
+                            Edge::Fall(x)
+                        } else {
+                            Edge::Jump(x)
+                        }
+                    })
+                    .collect();
+                block.indirect = is_indirect(&instruction);
+                let block = current.take().unwrap();
+                blocks.insert(block.start, block);
+            } else {
+                block.successors = vec![Edge::Fall(next)];
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+        Cfg { blocks }
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // Graphviz source with one node per block listing its instructions. Fall-through edges are
+    // plain and taken jumps bold; indirect jumps point at a single "?" node. Edges into words
+    // that aren't code are left out.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+        for block in self.blocks() {
+            let mut label = String::new();
+            for (address, instruction) in &block.instructions {
+                write!(label, "{:04}: {}\\l", address, instruction).unwrap();
+            }
+            writeln!(out, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+        let mut indirect = false;
+        for block in self.blocks() {
+            for edge in &block.successors {
+                if !self.blocks.contains_key(&edge.target()) {
+                    continue;
+                }
+                let style = match edge {
+                    Edge::Fall(_) => "",
+                    Edge::Jump(_) => " [style=bold]",
+                };
+                writeln!(out, "    b{} -> b{}{};", block.start, edge.target(), style).unwrap();
+            }
+            if block.indirect {
+                indirect = true;
+                writeln!(out, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+        if indirect {
+            writeln!(out, "    indirect [label=\"?\", shape=circle];").unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[test]
+fn test_cfg() {
+    // Loops printing [count] until it reaches zero, then returns through [ret].
+    let program = super::asm::assemble(
+        "
+        loop:   OUT [count]
+                ADD [count], #-1, [count]
+                JNZ [count], #loop
+                JZ #0, [ret]
+                HLT
+        count:  DATA 2
+        ret:    DATA 12
+        ",
+    )
+    .unwrap();
+    let cfg = Cfg::new(&program);
+    let starts: Vec<usize> = cfg.blocks().map(|b| b.start).collect();
+    assert_eq!(starts, vec![0, 9]);
+    assert_eq!(
+        cfg.block(0).unwrap().successors,
+        vec![Edge::Fall(9), Edge::Jump(0)]
+    );
+    assert!(cfg.block(9).unwrap().indirect);
+    assert!(cfg.block(9).unwrap().successors.is_empty());
+
+    // The HLT is only reached through the indirect jump.
+    assert_eq!(Cfg::speculative(&program).len(), 3);
+
+    assert_eq!(
+        cfg.to_dot(),
+        "digraph cfg {\n    node [shape=box, fontname=monospace];\n    \
+         b0 [label=\"0000: OUT [13]\\l0002: ADD [13], #-1, [13]\\l0006: JNZ [13], #0\\l\"];\n    \
+         b9 [label=\"0009: JZ #0, [14]\\l\"];\n    \
+         b0 -> b9;\n    b0 -> b0 [style=bold];\n    b9 -> indirect [style=dashed];\n    \
+         indirect [label=\"?\", shape=circle];\n}\n"
+    );
+}
@@ -0,0 +1,521 @@
+use std::mem;
+use std::sync::Arc;
+
+use super::cfg::Cfg;
+use super::{Arg, Instruction, IntCode, IntcodeError, RunState, StepResult};
+
+// How control leaves a compiled instruction.
+enum Flow {
+    Next,
+    // The instruction has set ip itself.
+    Jump,
+    // The instruction wrote into a compiled block, which has to be invalidated.
+    Written(usize),
+    Stop(RunState),
+}
+
+type OpFn = Box<dyn Fn(&mut IntCode) -> Result<Flow, IntcodeError> + Send + Sync>;
+
+struct Op {
+    run: OpFn,
+    next: usize,
+    word: isize,
+    writes: bool,
+}
+
+struct Block {
+    start: usize,
+    ops: Vec<Op>,
+}
+
+// An operand read with its addressing mode resolved at compile time where possible.
+#[derive(Clone, Copy)]
+enum Src {
+    Immediate(isize),
+    Position(usize),
+    Other(Arg),
+}
+
+impl Src {
+    fn new(arg: Arg) -> Src {
+        match arg {
+            Arg::Immediate(x) => Src::Immediate(x),
+            Arg::Parameter(x) if x >= 0 => Src::Position(x as usize),
+            arg => Src::Other(arg),
+        }
+    }
+
+    #[inline]
+    fn load(self, machine: &IntCode, word: isize) -> Result<isize, IntcodeError> {
+        match self {
+            Src::Immediate(x) => Ok(x),
+            Src::Position(address) => {
+                machine
+                    .memory
+                    .get(address)
+                    .ok_or(IntcodeError::MemoryLimit {
+                        ip: machine.ip,
+                        instruction: word,
+                        address,
+                    })
+            }
+            Src::Other(arg) => machine.read(&arg, word),
+        }
+    }
+}
+
+// The operand written through. Compiled code stores into memory directly; only the tracer and
+// the self-modification report need `IntCode::write`'s bookkeeping, and machines using those
+// never run compiled code.
+#[derive(Clone, Copy)]
+enum Dst {
+    Position(usize),
+    Other(Arg),
+}
+
+impl Dst {
+    fn new(arg: Arg) -> Dst {
+        match arg {
+            Arg::Parameter(x) if x >= 0 => Dst::Position(x as usize),
+            arg => Dst::Other(arg),
+        }
+    }
+
+    #[inline]
+    fn store(
+        self,
+        machine: &mut IntCode,
+        word: isize,
+        value: isize,
+        code: &[bool],
+    ) -> Result<Flow, IntcodeError> {
+        let address = match self {
+            Dst::Position(address) => address,
+            Dst::Other(arg) => machine.address(&arg, word)?,
+        };
+        machine.invalidate(address);
+        machine
+            .memory
+            .set(address, value)
+            .ok_or(IntcodeError::MemoryLimit {
+                ip: machine.ip,
+                instruction: word,
+                address,
+            })?;
+        Ok(match code.get(address) {
+            Some(true) => Flow::Written(address),
+            _ => Flow::Next,
+        })
+    }
+}
+
+fn binary<F>(ip: usize, word: isize, args: (Arg, Arg, Arg), code: Arc<[bool]>, f: F) -> OpFn
+where
+    F: Fn(&IntCode, isize, isize) -> Result<isize, IntcodeError> + Send + Sync + 'static,
+{
+    let (x, y, z) = (Src::new(args.0), Src::new(args.1), Dst::new(args.2));
+    Box::new(move |machine| {
+        machine.ip = ip;
+        let a = x.load(machine, word)?;
+        let b = y.load(machine, word)?;
+        let value = f(machine, a, b)?;
+        z.store(machine, word, value, &code)
+    })
+}
+
+fn branch(ip: usize, word: isize, x: Arg, y: Arg, on_zero: bool) -> OpFn {
+    let (x, y) = (Src::new(x), Src::new(y));
+    Box::new(move |machine| {
+        machine.ip = ip;
+        let condition = x.load(machine, word)?;
+        let target = y.load(machine, word)?;
+        if (condition == 0) == on_zero {
+            machine.jump(target, word)?;
+            Ok(Flow::Jump)
+        } else {
+            Ok(Flow::Next)
+        }
+    })
+}
+
+// `code` marks the words covered by compiled blocks.
+fn compile_op(ip: usize, instruction: Instruction, code: &Arc<[bool]>) -> Op {
+    let word = instruction.encode()[0];
+    let code = code.clone();
+    let mut writes = false;
+    let run = match instruction {
+        Instruction::Add(x, y, z) => binary(ip, word, (x, y, z), code, move |m, a, b| {
+            m.arithmetic(a.checked_add(b), a.wrapping_add(b), word)
+        }),
+        Instruction::Mul(x, y, z) => binary(ip, word, (x, y, z), code, move |m, a, b| {
+            m.arithmetic(a.checked_mul(b), a.wrapping_mul(b), word)
+        }),
+        Instruction::LessThan(x, y, z) => {
+            binary(ip, word, (x, y, z), code, |_, a, b| Ok((a < b) as isize))
+        }
+        Instruction::Equals(x, y, z) => {
+            binary(ip, word, (x, y, z), code, |_, a, b| Ok((a == b) as isize))
+        }
+        Instruction::JNZ(x, y) => branch(ip, word, x, y, false),
+        Instruction::JZ(x, y) => branch(ip, word, x, y, true),
+        Instruction::AdjustRelativeBase(x) => {
+            let x = Src::new(x);
+            Box::new(move |machine: &mut IntCode| {
+                machine.ip = ip;
+                let offset = x.load(machine, word)?;
+                machine.adjust_relative_base(offset, word)?;
+                Ok(Flow::Next)
+            })
+        }
+        // The rest are rare enough in hot loops to just hand to the interpreter's executor,
+        // which records its writes in `last_write`.
+        _ => {
+            writes = instruction.write_arg().is_some();
+            Box::new(move |machine: &mut IntCode| {
+                machine.ip = ip;
+                Ok(match machine.execute_untraced(&instruction)? {
+                    StepResult::Continue => Flow::Next,
+                    StepResult::Halted => Flow::Stop(RunState::Halted),
+                    StepResult::NeedInput => Flow::Stop(RunState::NeedInput),
+                    StepResult::Output(x) => Flow::Stop(RunState::Output(x)),
+                })
+            })
+        }
+    };
+    Op {
+        run,
+        next: ip + instruction.size(),
+        word,
+        writes,
+    }
+}
+
+// Compiled blocks are the basic blocks of the speculative CFG, additionally split before every
+// input and after every output: both can return from `run`, and the machine has to resume on
+// a block start to stay in compiled code.
+fn compile_blocks(program: &[isize]) -> Vec<Block> {
+    let mut layout: Vec<Vec<(usize, Instruction)>> = Vec::new();
+    for basic in Cfg::speculative(program).blocks() {
+        let mut block = Vec::new();
+        for &(address, instruction) in &basic.instructions {
+            if let Instruction::Input(_) = instruction {
+                if !block.is_empty() {
+                    layout.push(mem::take(&mut block));
+                }
+            }
+            block.push((address, instruction));
+            if let Instruction::Output(_) = instruction {
+                layout.push(mem::take(&mut block));
+            }
+        }
+        if !block.is_empty() {
+            layout.push(block);
+        }
+    }
+
+    let mut code = vec![false; program.len()];
+    for &(address, instruction) in layout.iter().flatten() {
+        for word in &mut code[address..address + instruction.size()] {
+            *word = true;
+        }
+    }
+    let code: Arc<[bool]> = code.into();
+    layout
+        .into_iter()
+        .map(|block| Block {
+            start: block[0].0,
+            ops: block
+                .into_iter()
+                .map(|(address, instruction)| compile_op(address, instruction, &code))
+                .collect(),
+        })
+        .collect()
+}
+
+// A machine whose code has been compiled ahead of time into basic blocks of closures.
+// Execution that lands anywhere else (the target of a computed jump that isn't a block start,
+// or a block whose words have since been written) falls back to the interpreter. The
+// observable behaviour, errors included, is exactly `IntCode`'s.
+pub struct Compiled {
+    machine: IntCode,
+    blocks: Vec<Block>,
+    // Block starting at each program address, and the blocks covering each program word.
+    starts: Vec<Option<usize>>,
+    owners: Vec<Vec<usize>>,
+    valid: Vec<bool>,
+    fallback_steps: usize,
+}
+
+impl Compiled {
+    pub fn new(program: &[isize]) -> Compiled {
+        Compiled::with_machine(IntCode::new(program))
+    }
+
+    // Compiles the machine's initial image. Blocks already written over are left to the
+    // interpreter.
+    pub fn with_machine(machine: IntCode) -> Compiled {
+        let image = machine.memory.image();
+        // The interpreter faults on instructions past the memory limit; leave those to it.
+        let blocks = if image.len() <= machine.memory.max_size() {
+            compile_blocks(image)
+        } else {
+            Vec::new()
+        };
+        let mut starts = vec![None; image.len()];
+        let mut owners = vec![Vec::new(); image.len()];
+        for (i, block) in blocks.iter().enumerate() {
+            starts[block.start] = Some(i);
+            let end = block.ops.last().unwrap().next;
+            for owner in &mut owners[block.start..end] {
+                owner.push(i);
+            }
+        }
+        let mut compiled = Compiled {
+            machine,
+            valid: vec![true; blocks.len()],
+            blocks,
+            starts,
+            owners,
+            fallback_steps: 0,
+        };
+        compiled.revalidate();
+        compiled
+    }
+
+    fn revalidate(&mut self) {
+        let memory = &self.machine.memory;
+        for (i, block) in self.blocks.iter().enumerate() {
+            let end = block.ops.last().unwrap().next;
+            self.valid[i] = (block.start..end).all(|a| memory[a] == memory.image()[a]);
+        }
+    }
+
+    pub fn machine(&self) -> &IntCode {
+        &self.machine
+    }
+
+    // Writes made through this can't be tracked, so every block drops to the interpreter
+    // until the next `reset`.
+    pub fn machine_mut(&mut self) -> &mut IntCode {
+        for valid in &mut self.valid {
+            *valid = false;
+        }
+        &mut self.machine
+    }
+
+    pub fn push_input(&mut self, value: isize) {
+        self.machine.push_input(value);
+    }
+
+    pub fn extend_input(&mut self, values: &[isize]) {
+        self.machine.extend_input(values);
+    }
+
+    pub fn reset(&mut self) {
+        self.machine.reset();
+        for valid in &mut self.valid {
+            *valid = true;
+        }
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    // Instructions run by the interpreter rather than compiled code, over the machine's life.
+    pub fn fallback_steps(&self) -> usize {
+        self.fallback_steps
+    }
+
+    // Invalidates any block covering the last write, returning whether there was one.
+    fn code_written(&mut self) -> bool {
+        match self.machine.last_write.take() {
+            Some((address, _)) => self.invalidate_owners(address),
+            None => false,
+        }
+    }
+
+    fn invalidate_owners(&mut self, address: usize) -> bool {
+        match self.owners.get(address) {
+            Some(owners) if !owners.is_empty() => {
+                for owner in owners {
+                    self.valid[*owner] = false;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn run_block(&mut self, index: usize) -> Result<Option<RunState>, IntcodeError> {
+        let count = self.blocks[index].ops.len();
+        for i in 0..count {
+            let op = &self.blocks[index].ops[i];
+            let (next, word, writes) = (op.next, op.word, op.writes);
+            let flow = (op.run)(&mut self.machine)?;
+            let written = match flow {
+                Flow::Written(address) => self.invalidate_owners(address),
+                _ => writes && self.code_written(),
+            };
+            match flow {
+                Flow::Next | Flow::Written(_) if written || i + 1 == count => {
+                    self.machine.jump(next as isize, word)?;
+                    return Ok(None);
+                }
+                Flow::Next | Flow::Written(_) => {}
+                Flow::Jump => return Ok(None),
+                Flow::Stop(state) => return Ok(Some(state)),
+            }
+        }
+        Ok(None)
+    }
+
+    // Same contract as `IntCode::run`. Traced, profiled or self-modification-tracked machines
+    // are run entirely by the interpreter so that every instruction is still observed, as are
+    // machines with a deadline or step limit.
+    pub fn run(&mut self) -> Result<RunState, IntcodeError> {
+        let machine = &self.machine;
+        let observed = machine.tracer.is_some() || machine.self_mod.is_some();
+        let limited = machine.deadline.is_some() || machine.step_budget.is_some();
+        if observed || limited || machine.profile.is_some() {
+            return self.machine.run();
+        }
+        loop {
+            if self.machine.halted {
+                return Ok(RunState::Halted);
+            }
+            let block = self.starts.get(self.machine.ip).cloned().flatten();
+            match block {
+                Some(block) if self.valid[block] => {
+                    if let Some(state) = self.run_block(block)? {
+                        return Ok(state);
+                    }
+                }
+                _ => {
+                    self.fallback_steps += 1;
+                    let result = self.machine.step()?;
+                    self.code_written();
+                    match result {
+                        StepResult::Continue => {}
+                        StepResult::Halted => return Ok(RunState::Halted),
+                        StepResult::NeedInput => return Ok(RunState::NeedInput),
+                        StepResult::Output(x) => return Ok(RunState::Output(x)),
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn run_to_halt(&mut self) -> Result<Vec<isize>, IntcodeError> {
+        let mut output = Vec::new();
+        loop {
+            match self.run()? {
+                RunState::Halted => return Ok(output),
+                RunState::Output(x) => output.push(x),
+                RunState::NeedInput => {
+                    return Err(IntcodeError::InputExhausted {
+                        ip: self.machine.ip,
+                        instruction: self.machine.fetch(0),
+                    })
+                }
+            }
+        }
+    }
+}
+
+// Runs `program` on both the interpreter and the compiler, feeding each `input` as it blocks,
+// and checks that every run state, register and memory cell agree along the way. Returns the
+// agreed outcome, or a description of the first divergence.
+pub fn differential(
+    program: &[isize],
+    input: &[isize],
+) -> Result<Result<Vec<isize>, IntcodeError>, String> {
+    let mut interpreter = IntCode::new(program);
+    let mut compiled = Compiled::new(program);
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    loop {
+        let expected = interpreter.run();
+        let actual = compiled.run();
+        let machine = &compiled.machine;
+        let state = |m: &IntCode| {
+            (
+                m.ip,
+                m.relative_base,
+                m.halted,
+                m.input.clone(),
+                m.memory.as_slice().to_vec(),
+                m.memory.sparse_entries(),
+            )
+        };
+        if expected != actual || state(&interpreter) != state(machine) {
+            return Err(format!(
+                "diverged after {} output(s): interpreter {:?} at ip {}, compiled {:?} at ip {}",
+                output.len(),
+                expected,
+                interpreter.ip,
+                actual,
+                machine.ip
+            ));
+        }
+        match expected {
+            Err(e) => return Ok(Err(e)),
+            Ok(RunState::Halted) => return Ok(Ok(output)),
+            Ok(RunState::Output(x)) => output.push(x),
+            Ok(RunState::NeedInput) => match input.next() {
+                Some(x) => {
+                    interpreter.push_input(*x);
+                    compiled.push_input(*x);
+                }
+                None => {
+                    return Ok(Err(IntcodeError::InputExhausted {
+                        ip: interpreter.ip,
+                        instruction: interpreter.fetch(0),
+                    }))
+                }
+            },
+        }
+    }
+}
+
+#[test]
+fn test_compile() {
+    use super::fixtures;
+
+    let fib = fixtures::fib();
+    assert_eq!(differential(&fib, &[15]), Ok(Ok(vec![610])));
+    // Returns are computed jumps, but they all land on compiled block starts.
+    let mut compiled = Compiled::new(&fib);
+    compiled.push_input(15);
+    assert_eq!(compiled.run_to_halt(), Ok(vec![610]));
+    assert_eq!(compiled.fallback_steps(), 0);
+
+    // Rewrites the operand of its own OUT, so that block drops to the interpreter.
+    let self_modifying = fixtures::self_modifying();
+    assert_eq!(differential(&self_modifying, &[]), Ok(Ok(vec![0, 1, 2])));
+    let mut compiled = Compiled::new(&self_modifying);
+    assert_eq!(compiled.run_to_halt(), Ok(vec![0, 1, 2]));
+    assert!(compiled.fallback_steps() > 0);
+    compiled.reset();
+    assert_eq!(compiled.run_to_halt(), Ok(vec![0, 1, 2]));
+
+    let quine = fixtures::quine();
+    assert_eq!(differential(&quine, &[]), Ok(Ok(quine.clone())));
+    let doubler = fixtures::doubler();
+    assert_eq!(differential(&doubler, &[1, 2, 3, 0]), Ok(Ok(vec![2, 4, 6])));
+    assert!(differential(&doubler, &[1]).unwrap().is_err());
+    assert!(differential(&fixtures::day2_example(), &[]).is_ok());
+
+    // Errors come out of compiled code identically.
+    for program in &[
+        vec![109, isize::MAX, 204, 1, 99],
+        vec![1, 0, 0, 0, 42],
+        vec![4, -3, 99],
+        vec![1105, 1, -1],
+        vec![1101, 2, 3, 1 << 40, 99],
+        vec![11101, 1, 1, 0, 99],
+    ] {
+        let outcome = differential(program, &[]).unwrap();
+        assert!(outcome.is_err(), "{:?}", program);
+    }
+}
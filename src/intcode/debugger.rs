@@ -0,0 +1,205 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use super::{Instruction, IntCode, IntcodeError, StepResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    // A single step finished without anything else to report.
+    Step,
+    Breakpoint(usize),
+    Watchpoint {
+        address: usize,
+        old: isize,
+        new: isize,
+    },
+    Output(isize),
+    NeedInput,
+    Halted,
+}
+
+pub struct Debugger {
+    machine: IntCode,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    output: Vec<isize>,
+    steps: usize,
+}
+
+impl Debugger {
+    pub fn new(program: &[isize]) -> Debugger {
+        Debugger::with_machine(IntCode::new(program))
+    }
+
+    pub fn with_machine(machine: IntCode) -> Debugger {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            output: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn machine(&self) -> &IntCode {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut IntCode {
+        &mut self.machine
+    }
+
+    pub fn output(&self) -> &[isize] {
+        &self.output
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &BTreeSet<usize> {
+        &self.watchpoints
+    }
+
+    // Returns false if the breakpoint was already set.
+    pub fn add_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.insert(ip)
+    }
+
+    pub fn remove_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.remove(&ip)
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.insert(address)
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    // Executes one instruction. A blocked or halted machine does not move.
+    pub fn step(&mut self) -> Result<Stop, IntcodeError> {
+        let watched: Vec<(usize, isize)> = self
+            .watchpoints
+            .iter()
+            .map(|a| (*a, self.machine.memory()[*a]))
+            .collect();
+        let result = self.machine.step()?;
+        match result {
+            StepResult::Halted => return Ok(Stop::Halted),
+            StepResult::NeedInput => return Ok(Stop::NeedInput),
+            _ => {}
+        }
+        self.steps += 1;
+        for (address, old) in watched {
+            let new = self.machine.memory()[address];
+            if new != old {
+                return Ok(Stop::Watchpoint { address, old, new });
+            }
+        }
+        if let StepResult::Output(x) = result {
+            self.output.push(x);
+            return Ok(Stop::Output(x));
+        }
+        Ok(Stop::Step)
+    }
+
+    // Runs until a breakpoint or watchpoint triggers, or the machine halts or blocks. The
+    // breakpoint at the current ip, if any, is stepped over. Outputs do not stop execution.
+    pub fn resume(&mut self) -> Result<Stop, IntcodeError> {
+        loop {
+            match self.step()? {
+                Stop::Step | Stop::Output(_) => {}
+                stop => return Ok(stop),
+            }
+            let ip = self.machine.ip();
+            if self.breakpoints.contains(&ip) {
+                return Ok(Stop::Breakpoint(ip));
+            }
+        }
+    }
+
+    pub fn current_instruction(&self) -> Result<Instruction, IntcodeError> {
+        self.machine.decode()
+    }
+
+    pub fn registers(&self) -> String {
+        let next = match self.current_instruction() {
+            Ok(instruction) => instruction.to_string(),
+            Err(e) => format!("<{}>", e),
+        };
+        format!(
+            "ip={:04} rb={} steps={} halted={} next: {}",
+            self.machine.ip(),
+            self.machine.relative_base(),
+            self.steps,
+            self.machine.halted(),
+            next
+        )
+    }
+
+    // Eight words per row, each row prefixed with its address. Stops at the end of the
+    // address space.
+    pub fn dump(&self, start: usize, len: usize) -> String {
+        let end = start.saturating_add(len);
+        let mut out = String::new();
+        for row in (start..end).step_by(8) {
+            write!(out, "{:04}:", row).unwrap();
+            for address in row..std::cmp::min(row.saturating_add(8), end) {
+                write!(out, " {}", self.machine.memory()[address]).unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[test]
+fn test_debugger() {
+    let program = super::asm::assemble(
+        "
+        loop:   IN [value]
+                MUL [value], #2, [value]
+                OUT [value]
+                JNZ [value], #loop
+                HLT
+        value:  DATA 0
+        ",
+    )
+    .unwrap();
+    let mut debugger = Debugger::new(&program);
+    debugger.machine_mut().extend_input(&[3, 4, 0]);
+    debugger.add_breakpoint(6);
+    assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(6)));
+    assert_eq!(debugger.step(), Ok(Stop::Output(6)));
+    assert_eq!(debugger.machine().input_queue().len(), 2);
+
+    debugger.remove_breakpoint(6);
+    debugger.add_watchpoint(12);
+    assert_eq!(
+        debugger.resume(),
+        Ok(Stop::Watchpoint {
+            address: 12,
+            old: 6,
+            new: 4
+        })
+    );
+    debugger.machine_mut().input_queue_mut().clear();
+    debugger.remove_watchpoint(12);
+    assert_eq!(debugger.resume(), Ok(Stop::NeedInput));
+    assert_eq!(debugger.output(), &[6, 8]);
+    assert_eq!(
+        debugger.registers(),
+        "ip=0000 rb=0 steps=8 halted=false next: IN [12]"
+    );
+    assert_eq!(debugger.dump(11, 2), "0011: 99 8\n");
+    assert_eq!(
+        debugger.dump(usize::MAX - 1, 16),
+        format!("{}: 0\n", usize::MAX - 1)
+    );
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::{Arg, Instruction, Word};
+
+impl<W: Word> fmt::Display for Arg<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Arg::Immediate(x) => write!(f, "#{}", x),
+            Arg::Parameter(x) => write!(f, "[{}]", x),
+            Arg::Relative(x) if x < 0 => write!(f, "[r{}]", x),
+            Arg::Relative(x) => write!(f, "[r+{}]", x),
+        }
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Halt => "HLT",
+            Instruction::Add(_, _, _) => "ADD",
+            Instruction::Mul(_, _, _) => "MUL",
+            Instruction::Input(_) => "IN",
+            Instruction::Output(_) => "OUT",
+            Instruction::JNZ(_, _) => "JNZ",
+            Instruction::JZ(_, _) => "JZ",
+            Instruction::LessThan(_, _, _) => "LT",
+            Instruction::Equals(_, _, _) => "EQ",
+            Instruction::AdjustRelativeBase(_) => "ARB",
+        }
+    }
+}
+
+impl Instruction {
+    // Statically known successors: the fall-through address (unless the instruction never
+    // falls through) and any immediate jump target.
+    pub fn successors(&self, address: usize) -> Vec<usize> {
+        let next = address + self.size();
+        let target = |arg: Arg| match arg {
+            Arg::Immediate(x) if x >= 0 => Some(x as usize),
+            _ => None,
+        };
+        match *self {
+            Instruction::Halt => vec![],
+            Instruction::JNZ(Arg::Immediate(x), y) if x != 0 => target(y).into_iter().collect(),
+            Instruction::JZ(Arg::Immediate(0), y) => target(y).into_iter().collect(),
+            Instruction::JNZ(Arg::Immediate(_), _) | Instruction::JZ(Arg::Immediate(_), _) => {
+                vec![next]
+            }
+            Instruction::JNZ(_, y) | Instruction::JZ(_, y) => {
+                let mut v = vec![next];
+                v.extend(target(y));
+                v
+            }
+            _ => vec![next],
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        for (i, arg) in self.args().iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, arg)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line {
+    // `reachable` is false for code found only by the linear sweep over unreached gaps.
+    Code {
+        address: usize,
+        instruction: Instruction,
+        reachable: bool,
+    },
+    Data {
+        address: usize,
+        value: isize,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match *self {
+            Line::Code { address, .. } | Line::Data { address, .. } => address,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Line::Code {
+                address,
+                instruction,
+                reachable,
+            } => {
+                write!(f, "{:04}: {}", address, instruction)?;
+                if !reachable {
+                    write!(f, " ; unreached")?;
+                }
+                Ok(())
+            }
+            Line::Data { address, value } => write!(f, "{:04}: DATA {}", address, value),
+        }
+    }
+}
+
+// Decodes the instruction at `address` if it lies entirely within `program` and re-encodes to
+// exactly the same words, so the listing always reassembles to the original program.
+pub fn decode_exact(program: &[isize], address: usize) -> Option<Instruction> {
+    let fetch = |a: usize| program.get(a).cloned().unwrap_or(0);
+    let instruction = Instruction::decode(address, fetch).ok()?;
+    let end = address + instruction.size();
+    if end > program.len() || instruction.encode()[..] != program[address..end] {
+        return None;
+    }
+    Some(instruction)
+}
+
+// Instruction start addresses reachable from address 0 by following fall-through and
+// immediate jump targets. Indirect jumps are not followed.
+pub fn reachable(program: &[isize]) -> BTreeSet<usize> {
+    let mut seen = BTreeSet::new();
+    let mut work = vec![0];
+    while let Some(address) = work.pop() {
+        if address >= program.len() || !seen.insert(address) {
+            continue;
+        }
+        match decode_exact(program, address) {
+            Some(instruction) => work.extend(instruction.successors(address)),
+            None => {
+                seen.remove(&address);
+            }
+        }
+    }
+    seen
+}
+
+pub fn disassemble(program: &[isize]) -> Vec<Line> {
+    let starts = reachable(program);
+    let mut covered = vec![false; program.len()];
+    for start in &starts {
+        let instruction = decode_exact(program, *start).unwrap();
+        for c in covered.iter_mut().skip(*start).take(instruction.size()) {
+            *c = true;
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        if starts.contains(&address) {
+            let instruction = decode_exact(program, address).unwrap();
+            lines.push(Line::Code {
+                address,
+                instruction,
+                reachable: true,
+            });
+            address += instruction.size();
+            continue;
+        }
+        // Linear sweep through the gap, never overlapping reachable code.
+        let swept = decode_exact(program, address).filter(|instruction| {
+            let end = address + instruction.size();
+            !covered[address..end].iter().any(|c| *c)
+        });
+        match swept {
+            Some(instruction) => {
+                lines.push(Line::Code {
+                    address,
+                    instruction,
+                    reachable: false,
+                });
+                address += instruction.size();
+            }
+            None => {
+                lines.push(Line::Data {
+                    address,
+                    value: program[address],
+                });
+                address += 1;
+            }
+        }
+    }
+    lines
+}
+
+pub fn listing(program: &[isize]) -> String {
+    let mut out = String::new();
+    for line in disassemble(program) {
+        out.push_str(&line.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn test_disassemble() {
+    let add = Instruction::decode(0, |a| [21101, 3, 4, 100][a]).unwrap();
+    assert_eq!(add.to_string(), "ADD #3, #4, [r+100]");
+    assert_eq!(add.encode(), vec![21101, 3, 4, 100]);
+    assert_eq!(
+        Line::Code {
+            address: 12,
+            instruction: Instruction::Add(Arg::Relative(3), Arg::Immediate(4), Arg::Parameter(100)),
+            reachable: true
+        }
+        .to_string(),
+        "0012: ADD [r+3], #4, [100]"
+    );
+
+    // The jump skips over two data words that would otherwise sweep as an ADD.
+    let program = [1105, 1, 5, 1, 0, 4, 3, 99, 7];
+    assert_eq!(
+        listing(&program),
+        "0000: JNZ #1, #5\n\
+         0003: DATA 1\n\
+         0004: DATA 0\n\
+         0005: OUT [3]\n\
+         0007: HLT\n\
+         0008: DATA 7\n"
+    );
+
+    let program = [1105, 1, 7, 1, 0, 0, 0, 99];
+    assert_eq!(
+        listing(&program),
+        "0000: JNZ #1, #7\n\
+         0003: ADD [0], [0], [0] ; unreached\n\
+         0007: HLT\n"
+    );
+}
@@ -0,0 +1,31 @@
+// Programs shared between tests. The benchmarks include the same files directly.
+use super::asm;
+
+// Reads n and prints fib(n) by naive recursion on the relative base.
+pub fn fib() -> Vec<isize> {
+    asm::assemble(include_str!("fixtures/fib.asm")).unwrap()
+}
+
+// Prints 0, 1, 2 by rewriting the operand of its own OUT.
+pub fn self_modifying() -> Vec<isize> {
+    asm::assemble(include_str!("fixtures/self_modifying.asm")).unwrap()
+}
+
+// The day 9 example that prints a copy of itself.
+pub fn quine() -> Vec<isize> {
+    vec![
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ]
+}
+
+// Doubles each input until it reads a zero.
+pub fn doubler() -> Vec<isize> {
+    vec![
+        3, 15, 1006, 15, 14, 102, 2, 15, 15, 4, 15, 1105, 1, 0, 99, 0,
+    ]
+}
+
+// The day 2 example, which writes over its own code.
+pub fn day2_example() -> Vec<isize> {
+    vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]
+}
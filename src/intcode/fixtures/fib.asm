@@ -0,0 +1,32 @@
+; Naive recursive Fibonacci: reads n and prints fib(n), with a stack frame per call on the
+; relative base. The same shape of workload as the day 9 part 2 BOOST program.
+        ARB #stack
+        IN [r+5]
+        ADD #done, #0, [r+4]
+        ARB #4
+        JZ #0, #fib
+done:   OUT [r+6]
+        HLT
+
+; frame: [r+0] return address, [r+1] n, [r+2] result, [r+3] fib(n-1)
+fib:    LT [r+1], #2, [cond]
+        JZ [cond], #recurse
+        ADD [r+1], #0, [r+2]
+        JZ #0, #return
+recurse: ADD #back1, #0, [r+4]
+        ADD [r+1], #-1, [r+5]
+        ARB #4
+        JZ #0, #fib
+back1:  ADD [r+6], #0, [r+3]
+        ADD #back2, #0, [r+4]
+        ADD [r+1], #-2, [r+5]
+        ARB #4
+        JZ #0, #fib
+back2:  ADD [r+3], [r+6], [r+2]
+return: ADD [r+0], #0, [ret]
+        ARB #-4
+        JZ #0, [ret]
+
+cond:   DATA 0
+ret:    DATA 0
+stack:  DATA 0
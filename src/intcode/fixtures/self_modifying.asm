@@ -0,0 +1,7 @@
+; Bumps the immediate operand of its own OUT three times, printing 0, 1, 2.
+loop:   OUT #0
+        ADD [loop+1], #1, [loop+1]
+        ADD [count], #-1, [count]
+        JNZ [count], #loop
+        HLT
+count:  DATA 3
@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+
+use super::Word;
+
+pub trait IntcodeIo<W = isize> {
+    // None means no input is available yet; the machine stops with `RunState::NeedInput`.
+    fn read(&mut self) -> Option<W>;
+    fn write(&mut self, value: W);
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueIo<W = isize> {
+    pub input: VecDeque<W>,
+    pub output: Vec<W>,
+}
+
+impl<W: Word> QueueIo<W> {
+    pub fn new(input: &[W]) -> QueueIo<W> {
+        QueueIo {
+            input: input.iter().cloned().collect(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> IntcodeIo<W> for QueueIo<W> {
+    fn read(&mut self) -> Option<W> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: W) {
+        self.output.push(value);
+    }
+}
+
+// Reads block until a value arrives unless `blocking` is false. Writes to a
+// disconnected receiver are dropped, as are reads from a disconnected sender.
+pub struct ChannelIo<W = isize> {
+    pub rx: Receiver<W>,
+    pub tx: Sender<W>,
+    pub blocking: bool,
+}
+
+impl<W: Word> ChannelIo<W> {
+    pub fn new(rx: Receiver<W>, tx: Sender<W>) -> ChannelIo<W> {
+        ChannelIo {
+            rx,
+            tx,
+            blocking: true,
+        }
+    }
+
+    pub fn non_blocking(rx: Receiver<W>, tx: Sender<W>) -> ChannelIo<W> {
+        ChannelIo {
+            rx,
+            tx,
+            blocking: false,
+        }
+    }
+}
+
+impl<W: Word> IntcodeIo<W> for ChannelIo<W> {
+    fn read(&mut self) -> Option<W> {
+        if self.blocking {
+            self.rx.recv().ok()
+        } else {
+            match self.rx.try_recv() {
+                Ok(x) => Some(x),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+            }
+        }
+    }
+
+    fn write(&mut self, value: W) {
+        let _ = self.tx.send(value);
+    }
+}
+
+pub struct FnIo<R, F> {
+    pub read: R,
+    pub write: F,
+}
+
+impl<R, F> FnIo<R, F> {
+    pub fn new<W: Word>(read: R, write: F) -> FnIo<R, F>
+    where
+        R: FnMut() -> Option<W>,
+        F: FnMut(W),
+    {
+        FnIo { read, write }
+    }
+}
+
+impl<R, F, W> IntcodeIo<W> for FnIo<R, F>
+where
+    W: Word,
+    R: FnMut() -> Option<W>,
+    F: FnMut(W),
+{
+    fn read(&mut self) -> Option<W> {
+        (self.read)()
+    }
+
+    fn write(&mut self, value: W) {
+        (self.write)(value)
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::ops::Index;
+use std::sync::Arc;
+
+use super::Word;
+
+pub const DEFAULT_MAX_SIZE: usize = 1 << 32;
+
+// Writes this close past the end of the dense region grow it; anything further
+// out goes into the sparse map so a single far-away scratch cell stays cheap.
+const GROW_SLACK: usize = 4096;
+
+#[derive(Clone, Debug)]
+pub struct Memory<W = isize> {
+    dense: Vec<W>,
+    sparse: HashMap<usize, W>,
+    max_size: usize,
+    // The initial contents, shared between clones, and the image addresses written since, so
+    // `reset` only has to undo those.
+    image: Arc<[W]>,
+    dirty: Vec<usize>,
+    is_dirty: Vec<bool>,
+    // What `Index` hands out for cells never written.
+    zero: W,
+}
+
+impl<W: Word> Memory<W> {
+    pub fn new(image: &[W]) -> Memory<W> {
+        Memory::with_max_size(image, DEFAULT_MAX_SIZE)
+    }
+
+    pub fn with_max_size(image: &[W], max_size: usize) -> Memory<W> {
+        Memory {
+            dense: image.to_vec(),
+            sparse: HashMap::new(),
+            max_size,
+            image: image.into(),
+            dirty: Vec::new(),
+            is_dirty: vec![false; image.len()],
+            zero: W::ZERO,
+        }
+    }
+
+    pub fn image(&self) -> &[W] {
+        &self.image
+    }
+
+    // Number of image addresses that differ from (or were written over) the initial contents.
+    pub fn dirty_len(&self) -> usize {
+        self.dirty.len()
+    }
+
+    // Restores the initial image, touching only the addresses written since the last reset.
+    pub fn reset(&mut self) {
+        for address in self.dirty.drain(..) {
+            self.dense[address] = self.image[address];
+            self.is_dirty[address] = false;
+        }
+        self.dense.truncate(self.image.len());
+        self.sparse.clear();
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    // The contiguous region starting at address 0.
+    pub fn as_slice(&self) -> &[W] {
+        &self.dense
+    }
+
+    pub fn sparse_len(&self) -> usize {
+        self.sparse.len()
+    }
+
+    // Sparse cells in ascending address order.
+    pub fn sparse_entries(&self) -> Vec<(usize, W)> {
+        let mut entries: Vec<(usize, W)> = self.sparse.iter().map(|(a, v)| (*a, *v)).collect();
+        entries.sort();
+        entries
+    }
+
+    // Returns None for addresses at or beyond the maximum size.
+    pub fn get(&self, address: usize) -> Option<W> {
+        if address >= self.max_size {
+            return None;
+        }
+        Some(match self.dense.get(address) {
+            Some(x) => *x,
+            None => self.sparse.get(&address).cloned().unwrap_or(W::ZERO),
+        })
+    }
+
+    // Returns None for addresses at or beyond the maximum size.
+    pub fn set(&mut self, address: usize, value: W) -> Option<()> {
+        if address >= self.max_size {
+            return None;
+        }
+        let len = self.dense.len();
+        if address < len {
+            if address < self.image.len() && !self.is_dirty[address] {
+                self.is_dirty[address] = true;
+                self.dirty.push(address);
+            }
+            self.dense[address] = value;
+        } else if address < len + GROW_SLACK || address < len * 2 {
+            self.grow(address + 1);
+            self.dense[address] = value;
+        } else {
+            self.sparse.insert(address, value);
+        }
+        Some(())
+    }
+
+    fn grow(&mut self, new_len: usize) {
+        let old_len = self.dense.len();
+        self.dense.resize(new_len, W::ZERO);
+        if !self.sparse.is_empty() {
+            let moved: Vec<usize> = self
+                .sparse
+                .keys()
+                .filter(|x| **x >= old_len && **x < new_len)
+                .cloned()
+                .collect();
+            for address in moved {
+                self.dense[address] = self.sparse.remove(&address).unwrap();
+            }
+        }
+    }
+}
+
+impl<W: Word> Index<usize> for Memory<W> {
+    type Output = W;
+
+    fn index(&self, address: usize) -> &W {
+        match self.dense.get(address) {
+            Some(x) => x,
+            None => self.sparse.get(&address).unwrap_or(&self.zero),
+        }
+    }
+}
+
+#[test]
+fn test_memory() {
+    let mut memory: Memory = Memory::with_max_size(&[1, 2, 3], 1 << 20);
+    assert_eq!(memory.get(100), Some(0));
+    memory.set(100, 7).unwrap();
+    assert_eq!(memory.as_slice().len(), 101);
+    memory.set(500_000, 9).unwrap();
+    assert_eq!(memory.sparse_len(), 1);
+    assert_eq!(memory[500_000], 9);
+    assert_eq!(memory.set(1 << 20, 1), None);
+    assert_eq!(memory.get(1 << 20), None);
+
+    // Growing the dense region absorbs sparse cells it now covers.
+    let mut memory: Memory = Memory::new(&[1, 2, 3]);
+    memory.set(6000, 5).unwrap();
+    assert_eq!(memory.sparse_len(), 1);
+    memory.set(4000, 1).unwrap();
+    memory.set(6500, 2).unwrap();
+    assert_eq!(memory.sparse_len(), 0);
+    assert_eq!(memory.as_slice()[6000], 5);
+}
+
+#[test]
+fn test_memory_reset() {
+    let mut memory: Memory = Memory::new(&[1, 2, 3]);
+    memory.set(1, 20).unwrap();
+    memory.set(1, 21).unwrap();
+    memory.set(50, 5).unwrap();
+    memory.set(1_000_000, 6).unwrap();
+    assert_eq!(memory.dirty_len(), 1);
+
+    let copy = memory.clone();
+    memory.reset();
+    assert_eq!(memory.as_slice(), &[1, 2, 3]);
+    assert_eq!(memory[1_000_000], 0);
+    assert_eq!(memory.dirty_len(), 0);
+    assert_eq!(copy[1], 21);
+    assert_eq!(copy.image(), &[1, 2, 3]);
+}
@@ -0,0 +1,307 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use super::{IntCode, IntcodeError, RunState};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    // Node i feeds node i + 1.
+    Pipeline,
+    // A pipeline whose last node feeds back into the first.
+    Ring,
+    // Arbitrary directed (from, to) edges; an output is copied along every outgoing edge.
+    Graph(Vec<(usize, usize)>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkState {
+    Halted,
+    // Every machine that has not halted is blocked on input nobody will send.
+    Deadlock,
+}
+
+pub struct Network {
+    machines: Vec<IntCode>,
+    edges: Vec<Vec<usize>>,
+    outputs: Vec<Vec<isize>>,
+}
+
+impl Network {
+    pub fn new(machines: Vec<IntCode>, topology: Topology) -> Network {
+        let n = machines.len();
+        let pairs = match topology {
+            Topology::Pipeline => (1..n).map(|i| (i - 1, i)).collect(),
+            Topology::Ring => (0..n).map(|i| (i, (i + 1) % n)).collect(),
+            Topology::Graph(pairs) => pairs,
+        };
+        let mut edges = vec![Vec::new(); n];
+        for (from, to) in pairs {
+            assert!(from < n && to < n, "edge {}->{} outside network", from, to);
+            edges[from].push(to);
+        }
+        Network {
+            machines,
+            edges,
+            outputs: vec![Vec::new(); n],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machine(&self, node: usize) -> &IntCode {
+        &self.machines[node]
+    }
+
+    pub fn machine_mut(&mut self, node: usize) -> &mut IntCode {
+        &mut self.machines[node]
+    }
+
+    pub fn edges(&self, node: usize) -> &[usize] {
+        &self.edges[node]
+    }
+
+    // Resets every machine to its initial image and forgets all outputs.
+    pub fn reset(&mut self) {
+        for machine in self.machines.iter_mut() {
+            machine.reset();
+        }
+        for outputs in self.outputs.iter_mut() {
+            outputs.clear();
+        }
+    }
+
+    pub fn push_input(&mut self, node: usize, value: isize) {
+        self.machines[node].push_input(value);
+    }
+
+    // Everything `node` has emitted so far, whether or not it was routed anywhere.
+    pub fn outputs(&self, node: usize) -> &[isize] {
+        &self.outputs[node]
+    }
+
+    pub fn last_output(&self, node: usize) -> Option<isize> {
+        self.outputs[node].last().cloned()
+    }
+
+    // Runs the machines round-robin, each until it blocks, until all halt or none can progress.
+    pub fn run(&mut self) -> Result<NetworkState, IntcodeError> {
+        loop {
+            let mut progressed = false;
+            for node in 0..self.machines.len() {
+                if self.machines[node].halted() {
+                    continue;
+                }
+                loop {
+                    match self.machines[node].run()? {
+                        RunState::Output(x) => {
+                            progressed = true;
+                            self.route(node, x);
+                        }
+                        RunState::Halted => {
+                            progressed = true;
+                            break;
+                        }
+                        RunState::NeedInput => break,
+                    }
+                }
+            }
+            if self.machines.iter().all(|m| m.halted()) {
+                return Ok(NetworkState::Halted);
+            }
+            if !progressed {
+                return Ok(NetworkState::Deadlock);
+            }
+        }
+    }
+
+    // Runs every machine on its own thread, connected by channels along the edges. Returns
+    // `Deadlock` once every live machine is blocked on an empty channel.
+    pub fn run_threaded(&mut self) -> Result<NetworkState, IntcodeError> {
+        let n = self.machines.len();
+        let mut senders = Vec::with_capacity(n);
+        let mut receivers = Vec::with_capacity(n);
+        for _ in 0..n {
+            let (tx, rx) = channel();
+            senders.push(tx);
+            receivers.push(rx);
+        }
+        let shared = Shared {
+            state: Mutex::new(SharedState {
+                live: self.machines.iter().filter(|m| !m.halted()).count(),
+                blocked: vec![false; n],
+                pending: vec![0; n],
+                stop: false,
+            }),
+            wake: Condvar::new(),
+        };
+        let edges = &self.edges;
+        let machines = &mut self.machines;
+        let outputs = &mut self.outputs;
+        let results: Vec<Result<bool, IntcodeError>> = thread::scope(|scope| {
+            let handles: Vec<_> = machines
+                .iter_mut()
+                .zip(outputs.iter_mut())
+                .zip(receivers)
+                .enumerate()
+                .map(|(node, ((machine, outputs), rx))| {
+                    let targets: Vec<(usize, Sender<isize>)> = edges[node]
+                        .iter()
+                        .map(|to| (*to, senders[*to].clone()))
+                        .collect();
+                    let shared = &shared;
+                    scope.spawn(move || {
+                        let result = run_node(node, machine, outputs, rx, &targets, shared);
+                        if result.is_err() {
+                            shared.state.lock().unwrap().stop = true;
+                            shared.wake.notify_all();
+                        }
+                        result
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut deadlock = false;
+        for result in results {
+            deadlock |= result?;
+        }
+        if deadlock {
+            Ok(NetworkState::Deadlock)
+        } else {
+            Ok(NetworkState::Halted)
+        }
+    }
+
+    fn route(&mut self, node: usize, value: isize) {
+        self.outputs[node].push(value);
+        for i in 0..self.edges[node].len() {
+            let to = self.edges[node][i];
+            self.machines[to].push_input(value);
+        }
+    }
+}
+
+struct SharedState {
+    live: usize,
+    blocked: Vec<bool>,
+    // Values sent to each node but not yet received, so a blocked node knows one is on the way.
+    pending: Vec<usize>,
+    stop: bool,
+}
+
+impl SharedState {
+    // Every live node is blocked and none has a value on the way. A node that has been sent a
+    // value but not yet woken up still counts as blocked, so check its pending count too.
+    fn deadlocked(&self) -> bool {
+        let blocked = self.blocked.iter().filter(|x| **x).count();
+        blocked == self.live
+            && self
+                .blocked
+                .iter()
+                .zip(self.pending.iter())
+                .all(|(blocked, pending)| !blocked || *pending == 0)
+    }
+}
+
+struct Shared {
+    state: Mutex<SharedState>,
+    wake: Condvar,
+}
+
+// Returns Ok(true) if the node gave up because the network deadlocked.
+fn run_node(
+    node: usize,
+    machine: &mut IntCode,
+    outputs: &mut Vec<isize>,
+    rx: Receiver<isize>,
+    targets: &[(usize, Sender<isize>)],
+    shared: &Shared,
+) -> Result<bool, IntcodeError> {
+    if machine.halted() {
+        return Ok(false);
+    }
+    loop {
+        match machine.run()? {
+            RunState::Output(x) => {
+                outputs.push(x);
+                let mut state = shared.state.lock().unwrap();
+                for (to, tx) in targets {
+                    if tx.send(x).is_ok() {
+                        state.pending[*to] += 1;
+                    }
+                }
+                shared.wake.notify_all();
+            }
+            RunState::Halted => {
+                let mut state = shared.state.lock().unwrap();
+                state.live -= 1;
+                shared.wake.notify_all();
+                return Ok(false);
+            }
+            RunState::NeedInput => {
+                let mut state = shared.state.lock().unwrap();
+                state.blocked[node] = true;
+                while state.pending[node] == 0 && !state.stop {
+                    if state.deadlocked() {
+                        state.stop = true;
+                        shared.wake.notify_all();
+                        break;
+                    }
+                    state = shared.wake.wait(state).unwrap();
+                }
+                state.blocked[node] = false;
+                if state.pending[node] == 0 {
+                    return Ok(true);
+                }
+                state.pending[node] -= 1;
+                drop(state);
+                machine.push_input(rx.recv().unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_network() {
+    // Adds one to each input and passes it on, halting after five values.
+    let program = [
+        1101, 0, 5, 20, 3, 21, 1001, 21, 1, 21, 4, 21, 1001, 20, -1, 20, 1005, 20, 4, 99, 0, 0,
+    ];
+    let machines = (0..3).map(|_| IntCode::new(&program)).collect();
+    let mut network = Network::new(machines, Topology::Ring);
+    network.push_input(0, 0);
+    assert_eq!(network.run(), Ok(NetworkState::Halted));
+    assert_eq!(network.outputs(2), &[3, 6, 9, 12, 15]);
+
+    let machines = (0..3).map(|_| IntCode::new(&program)).collect();
+    let mut network = Network::new(machines, Topology::Graph(vec![(0, 1), (0, 2)]));
+    network.push_input(0, 10);
+    assert_eq!(network.run(), Ok(NetworkState::Deadlock));
+    assert_eq!(network.outputs(1), &[12]);
+    assert_eq!(network.outputs(2), &[12]);
+}
+
+#[test]
+fn test_network_threaded() {
+    let program = [
+        1101, 0, 5, 20, 3, 21, 1001, 21, 1, 21, 4, 21, 1001, 20, -1, 20, 1005, 20, 4, 99, 0, 0,
+    ];
+    let machines = (0..4).map(|_| IntCode::new(&program)).collect();
+    let mut network = Network::new(machines, Topology::Ring);
+    network.push_input(0, 0);
+    assert_eq!(network.run_threaded(), Ok(NetworkState::Halted));
+    assert_eq!(network.outputs(3), &[4, 8, 12, 16, 20]);
+
+    let machines = (0..3).map(|_| IntCode::new(&program)).collect();
+    let mut network = Network::new(machines, Topology::Pipeline);
+    network.push_input(0, 10);
+    assert_eq!(network.run_threaded(), Ok(NetworkState::Deadlock));
+    assert_eq!(network.outputs(2), &[13]);
+}
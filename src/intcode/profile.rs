@@ -0,0 +1,172 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::{Instruction, Word};
+
+const OPCODES: usize = 10;
+
+fn opcode_index<W: Word>(instruction: &Instruction<W>) -> usize {
+    match instruction {
+        Instruction::Halt => 0,
+        Instruction::Add(_, _, _) => 1,
+        Instruction::Mul(_, _, _) => 2,
+        Instruction::Input(_) => 3,
+        Instruction::Output(_) => 4,
+        Instruction::JNZ(_, _) => 5,
+        Instruction::JZ(_, _) => 6,
+        Instruction::LessThan(_, _, _) => 7,
+        Instruction::Equals(_, _, _) => 8,
+        Instruction::AdjustRelativeBase(_) => 9,
+    }
+}
+
+const MNEMONICS: [&str; OPCODES] = [
+    "HLT", "ADD", "MUL", "IN", "OUT", "JNZ", "JZ", "LT", "EQ", "ARB",
+];
+
+// Execution counts per instruction address and per opcode.
+#[derive(Clone, Debug)]
+pub struct Profile<W = isize> {
+    // Count and the instruction last executed at each address.
+    by_address: HashMap<usize, (u64, Instruction<W>)>,
+    by_opcode: [u64; OPCODES],
+    total: u64,
+}
+
+impl<W: Word> Default for Profile<W> {
+    fn default() -> Profile<W> {
+        Profile {
+            by_address: HashMap::new(),
+            by_opcode: [0; OPCODES],
+            total: 0,
+        }
+    }
+}
+
+impl<W: Word> Profile<W> {
+    pub fn new() -> Profile<W> {
+        Profile::default()
+    }
+
+    pub(super) fn record(&mut self, ip: usize, instruction: &Instruction<W>) {
+        let entry = self.by_address.entry(ip).or_insert((0, *instruction));
+        entry.0 += 1;
+        entry.1 = *instruction;
+        self.by_opcode[opcode_index(instruction)] += 1;
+        self.total += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn count(&self, address: usize) -> u64 {
+        self.by_address.get(&address).map_or(0, |x| x.0)
+    }
+
+    // Mnemonic and count for each opcode executed, most frequent first.
+    pub fn by_opcode(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<(&'static str, u64)> = MNEMONICS
+            .iter()
+            .cloned()
+            .zip(self.by_opcode.iter().cloned())
+            .filter(|x| x.1 > 0)
+            .collect();
+        counts.sort_by_key(|x| Reverse(x.1));
+        counts
+    }
+
+    // The `n` most executed addresses, with the instruction last executed there. Ties go to the
+    // lower address.
+    pub fn hot_spots(&self, n: usize) -> Vec<(usize, Instruction<W>, u64)> {
+        let mut spots: Vec<(usize, Instruction<W>, u64)> = self
+            .by_address
+            .iter()
+            .map(|(&address, &(count, instruction))| (address, instruction, count))
+            .collect();
+        spots.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        spots.truncate(n);
+        spots
+    }
+
+    pub fn report(&self, n: usize) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let mut out = String::new();
+        writeln!(out, "{} instructions executed", self.total).unwrap();
+        writeln!(out, "by opcode:").unwrap();
+        for (mnemonic, count) in self.by_opcode() {
+            writeln!(
+                out,
+                "  {:<4}{:>12} {:5.1}%",
+                mnemonic,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+        writeln!(out, "hot spots:").unwrap();
+        for (address, instruction, count) in self.hot_spots(n) {
+            let line = format!("{:04}: {}", address, instruction);
+            writeln!(out, "  {:<32}{:>12} {:5.1}%", line, count, percent(count)).unwrap();
+        }
+        out
+    }
+}
+
+#[test]
+fn test_profile() {
+    use super::IntCode;
+
+    let program = super::asm::assemble(
+        "
+        loop:   ADD [count], #-1, [count]
+                JNZ [count], #loop
+                OUT [count]
+                HLT
+        count:  DATA 3
+        ",
+    )
+    .unwrap();
+    let mut machine = IntCode::new(&program);
+    machine.set_profiling(true);
+    machine.run_to_halt().unwrap();
+    let profile = machine.profile().unwrap();
+    assert_eq!(profile.total(), 8);
+    assert_eq!(profile.count(0), 3);
+    assert_eq!(profile.count(1), 0);
+    assert_eq!(
+        profile.by_opcode(),
+        vec![("ADD", 3), ("JNZ", 3), ("HLT", 1), ("OUT", 1)]
+    );
+    assert_eq!(
+        profile.report(2),
+        "8 instructions executed\n\
+         by opcode:\n  \
+           ADD            3  37.5%\n  \
+           JNZ            3  37.5%\n  \
+           HLT            1  12.5%\n  \
+           OUT            1  12.5%\n\
+         hot spots:\n  \
+           0000: ADD [10], #-1, [10]                  3  37.5%\n  \
+           0004: JNZ [10], #0                         3  37.5%\n"
+    );
+
+    // Counts accumulate across resets, and a blocked input is only counted once it completes.
+    machine.reset();
+    machine.run_to_halt().unwrap();
+    assert_eq!(machine.profile().unwrap().total(), 16);
+    let mut machine = IntCode::new(&[3, 0, 99]);
+    machine.set_profiling(true);
+    assert_eq!(machine.run(), Ok(super::RunState::NeedInput));
+    assert_eq!(machine.profile().unwrap().total(), 0);
+
+    // Code running at a far address doesn't need a count for every address below it.
+    let mut machine = IntCode::new(&[1101, 99, 0, 100_000_000, 1105, 1, 100_000_000]);
+    machine.set_profiling(true);
+    machine.run_to_halt().unwrap();
+    let profile = machine.profile().unwrap();
+    assert_eq!(profile.count(100_000_000), 1);
+    let spots: Vec<usize> = profile.hot_spots(3).iter().map(|x| x.0).collect();
+    assert_eq!(spots, vec![0, 4, 100_000_000]);
+}
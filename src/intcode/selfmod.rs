@@ -0,0 +1,104 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+use super::{Instruction, Word};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub count: usize,
+    // Addresses of the instructions that made the writes.
+    pub writers: BTreeSet<usize>,
+}
+
+// Writes that landed on a word of an instruction that had already executed, by address.
+#[derive(Clone, Debug, Default)]
+pub struct SelfModReport {
+    executed: HashSet<usize>,
+    writes: BTreeMap<usize, CodeWrite>,
+}
+
+impl SelfModReport {
+    pub fn new() -> SelfModReport {
+        SelfModReport::default()
+    }
+
+    pub fn writes(&self) -> &BTreeMap<usize, CodeWrite> {
+        &self.writes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    // Every word the instruction occupies counts as code, operands included.
+    pub(super) fn executed<W: Word>(&mut self, ip: usize, instruction: &Instruction<W>) {
+        self.executed.extend(ip..ip + instruction.size());
+    }
+
+    pub(super) fn write(&mut self, ip: usize, address: usize) {
+        if self.executed.contains(&address) {
+            let write = self.writes.entry(address).or_default();
+            write.count += 1;
+            write.writers.insert(ip);
+        }
+    }
+}
+
+impl fmt::Display for SelfModReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} of {} executed code word(s) overwritten",
+            self.writes.len(),
+            self.executed.len()
+        )?;
+        for (address, write) in &self.writes {
+            let writers: Vec<String> = write.writers.iter().map(|x| format!("{:04}", x)).collect();
+            writeln!(
+                f,
+                "{:04}: {} write(s) from {}",
+                address,
+                write.count,
+                writers.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_self_modification() {
+    use super::IntCode;
+
+    let program = super::fixtures::self_modifying();
+    let mut machine = IntCode::new(&program);
+    machine.track_self_modification(true);
+    machine.run_to_halt().unwrap();
+    let report = "1 of 14 executed code word(s) overwritten\n0001: 3 write(s) from 0002\n";
+    assert_eq!(machine.self_modification().unwrap().to_string(), report);
+
+    // A reset starts a fresh report rather than adding to the old one.
+    machine.reset();
+    assert!(machine.self_modification().unwrap().is_empty());
+    machine.run_to_halt().unwrap();
+    assert_eq!(machine.self_modification().unwrap().to_string(), report);
+
+    // Day 2's example writes its result over the operand and opcode of earlier instructions.
+    let mut machine = IntCode::new(&super::fixtures::day2_example());
+    machine.track_self_modification(true);
+    machine.run_to_halt().unwrap();
+    let report = machine.self_modification().unwrap();
+    assert_eq!(
+        report.to_string(),
+        "2 of 9 executed code word(s) overwritten\n\
+         0000: 1 write(s) from 0004\n\
+         0003: 1 write(s) from 0000\n"
+    );
+    assert_eq!(report.writes()[&3].writers, [0].iter().cloned().collect());
+
+    // Programs that only write data report nothing.
+    let mut machine = IntCode::new(&[1101, 1, 2, 5, 99, 0]);
+    machine.track_self_modification(true);
+    machine.run_to_halt().unwrap();
+    assert!(machine.self_modification().unwrap().is_empty());
+}
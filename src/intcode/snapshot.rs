@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+use super::{IntCode, Memory};
+
+// Snapshots are plain text so they stay readable and diffable:
+//
+//     intcode-snapshot 1
+//     ip 12
+//     relative_base 0
+//     halted 0
+//     trap_overflow 1
+//     max_size 4294967296
+//     input 5 7
+//     image 4
+//     1,2,3,0
+//     memory 4
+//     1,2,3,4
+//     sparse 1
+//     100000 42
+//
+// `image` is the program `IntCode::reset` goes back to. Run limits, the tracer and the
+// profiling and self-modification reports are not saved.
+const HEADER: &str = "intcode-snapshot 1";
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn join(values: &[isize], sep: &str) -> String {
+    let values: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+    values.join(sep)
+}
+
+struct Lines<R: BufRead> {
+    input: R,
+    line: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn next(&mut self) -> io::Result<String> {
+        let mut text = String::new();
+        if self.input.read_line(&mut text)? == 0 {
+            return Err(invalid(format!(
+                "line {}: unexpected end of snapshot",
+                self.line + 1
+            )));
+        }
+        self.line += 1;
+        Ok(text.trim_end().to_string())
+    }
+
+    // Reads `<key> <rest>` and returns rest.
+    fn field(&mut self, key: &str) -> io::Result<String> {
+        let text = self.next()?;
+        let mut parts = text.splitn(2, ' ');
+        if parts.next() != Some(key) {
+            return Err(invalid(format!("line {}: expected '{}'", self.line, key)));
+        }
+        Ok(parts.next().unwrap_or("").to_string())
+    }
+
+    fn number<T: std::str::FromStr>(&self, text: &str) -> io::Result<T> {
+        text.trim()
+            .parse()
+            .map_err(|_| invalid(format!("line {}: bad number '{}'", self.line, text)))
+    }
+
+    // Reads `<key> <count>` followed by a line of that many comma-separated words.
+    fn words(&mut self, key: &str) -> io::Result<Vec<isize>> {
+        let len = self.field(key)?;
+        let len: usize = self.number(&len)?;
+        let text = self.next()?;
+        let words: Vec<isize> = self.numbers(&text, ',')?;
+        if words.len() != len {
+            return Err(invalid(format!(
+                "line {}: expected {} {} words, found {}",
+                self.line,
+                len,
+                key,
+                words.len()
+            )));
+        }
+        Ok(words)
+    }
+
+    fn numbers<T: std::str::FromStr>(&self, text: &str, sep: char) -> io::Result<Vec<T>> {
+        text.split(sep)
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| self.number(x))
+            .collect()
+    }
+}
+
+impl IntCode {
+    pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let input: Vec<isize> = self.input.iter().cloned().collect();
+        let sparse = self.memory.sparse_entries();
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "ip {}", self.ip)?;
+        writeln!(out, "relative_base {}", self.relative_base)?;
+        writeln!(out, "halted {}", self.halted as u8)?;
+        writeln!(out, "trap_overflow {}", self.trap_overflow as u8)?;
+        writeln!(out, "max_size {}", self.memory.max_size())?;
+        writeln!(out, "input {}", join(&input, " "))?;
+        writeln!(out, "image {}", self.memory.image().len())?;
+        writeln!(out, "{}", join(self.memory.image(), ","))?;
+        writeln!(out, "memory {}", self.memory.as_slice().len())?;
+        writeln!(out, "{}", join(self.memory.as_slice(), ","))?;
+        writeln!(out, "sparse {}", sparse.len())?;
+        for (address, value) in sparse {
+            writeln!(out, "{} {}", address, value)?;
+        }
+        out.flush()
+    }
+
+    pub fn load<R: BufRead>(input: R) -> io::Result<IntCode> {
+        let mut lines = Lines { input, line: 0 };
+        if lines.next()? != HEADER {
+            return Err(invalid("not an Intcode snapshot".to_string()));
+        }
+        let ip = lines.field("ip")?;
+        let ip: usize = lines.number(&ip)?;
+        let relative_base = lines.field("relative_base")?;
+        let relative_base: isize = lines.number(&relative_base)?;
+        let halted = lines.field("halted")?;
+        let halted: u8 = lines.number(&halted)?;
+        let trap_overflow = lines.field("trap_overflow")?;
+        let trap_overflow: u8 = lines.number(&trap_overflow)?;
+        let max_size = lines.field("max_size")?;
+        let max_size: usize = lines.number(&max_size)?;
+        let input = lines.field("input")?;
+        let input: VecDeque<isize> = lines.numbers(&input, ' ')?.into_iter().collect();
+        let image = lines.words("image")?;
+        if image.len() > max_size {
+            return Err(invalid(format!(
+                "line {}: image exceeds max_size",
+                lines.line
+            )));
+        }
+        let dense = lines.words("memory")?;
+        let mut memory = Memory::with_max_size(&image, max_size);
+        // Written in address order, so words past the image land in dense memory again.
+        for (address, value) in dense.iter().enumerate() {
+            if memory.image().get(address) != Some(value) && memory.set(address, *value).is_none() {
+                return Err(invalid(format!(
+                    "line {}: memory exceeds max_size",
+                    lines.line
+                )));
+            }
+        }
+        let count = lines.field("sparse")?;
+        let count: usize = lines.number(&count)?;
+        for _ in 0..count {
+            let entry = lines.next()?;
+            let entry: Vec<isize> = lines.numbers(&entry, ' ')?;
+            if entry.len() != 2 || entry[0] < 0 || memory.set(entry[0] as usize, entry[1]).is_none()
+            {
+                return Err(invalid(format!("line {}: bad sparse entry", lines.line)));
+            }
+        }
+
+        let mut machine = IntCode::with_memory_limit(&[], max_size);
+        machine.memory = memory;
+        machine.ip = ip;
+        machine.relative_base = relative_base;
+        machine.halted = halted != 0;
+        machine.trap_overflow = trap_overflow != 0;
+        machine.input = input;
+        Ok(machine)
+    }
+}
+
+#[test]
+fn test_snapshot() {
+    let program = super::asm::assemble(
+        "
+        IN [100000]
+        ARB #20
+        IN [r+7]
+        OUT [r+7]
+        OUT [100000]
+        HLT
+        ",
+    )
+    .unwrap();
+    let mut machine = IntCode::new(&program);
+    machine.extend_input(&[42]);
+    assert_eq!(machine.run(), Ok(super::RunState::NeedInput));
+
+    let mut saved = Vec::new();
+    machine.save(&mut saved).unwrap();
+    let mut restored = IntCode::load(&saved[..]).unwrap();
+    let mut branch = machine.clone();
+
+    let mut again = Vec::new();
+    restored.save(&mut again).unwrap();
+    assert_eq!(saved, again);
+
+    machine.push_input(1);
+    restored.push_input(1);
+    branch.push_input(2);
+    assert_eq!(machine.run_to_halt().unwrap(), vec![1, 42]);
+    assert_eq!(restored.run_to_halt().unwrap(), vec![1, 42]);
+    assert_eq!(branch.run_to_halt().unwrap(), vec![2, 42]);
+    assert_eq!(restored.memory()[27], 1);
+    assert_eq!(branch.memory()[27], 2);
+
+    assert!(IntCode::load(&b"intcode-snapshot 1\nip x\n"[..]).is_err());
+
+    // The overflow trap and the original program survive a round trip.
+    let mut machine = IntCode::new(&[3, 0, 1102, 1 << 62, 4, 9, 4, 9, 99, 0]);
+    machine.set_trap_overflow(true);
+    assert_eq!(machine.run(), Ok(super::RunState::NeedInput));
+    let mut saved = Vec::new();
+    machine.save(&mut saved).unwrap();
+    let mut restored = IntCode::load(&saved[..]).unwrap();
+    assert!(restored.trap_overflow());
+    restored.push_input(1);
+    assert!(restored.run_to_halt().is_err());
+    restored.reset();
+    assert_eq!(restored.memory()[0], 3);
+    assert_eq!(restored.run(), Ok(super::RunState::NeedInput));
+
+    let oversized = "intcode-snapshot 1\nip 0\nrelative_base 0\nhalted 0\ntrap_overflow 0\n\
+                     max_size 2\ninput \nimage 3\n104,7,99\nmemory 3\n104,7,99\nsparse 0\n";
+    assert_eq!(
+        IntCode::load(oversized.as_bytes())
+            .err()
+            .unwrap()
+            .to_string(),
+        "line 9: image exceeds max_size"
+    );
+}
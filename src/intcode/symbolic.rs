@@ -0,0 +1,330 @@
+use std::iter;
+use std::ops::Range;
+
+use itertools::Itertools;
+
+use super::{Arg, Instruction};
+
+// Symbolic execution gives up after this many steps, or when addressing beyond this size.
+const MAX_STEPS: usize = 1_000_000;
+const MAX_ADDRESS: usize = 1 << 20;
+
+// `constant + sum(coefficients[i] * unknown_i)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Linear {
+    pub constant: isize,
+    pub coefficients: Vec<isize>,
+}
+
+impl Linear {
+    fn constant(constant: isize, unknowns: usize) -> Linear {
+        Linear {
+            constant,
+            coefficients: vec![0; unknowns],
+        }
+    }
+
+    fn unknown(index: usize, unknowns: usize) -> Linear {
+        let mut linear = Linear::constant(0, unknowns);
+        linear.coefficients[index] = 1;
+        linear
+    }
+
+    pub fn as_constant(&self) -> Option<isize> {
+        if self.coefficients.iter().all(|c| *c == 0) {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    // Arithmetic is checked: an overflow makes the expression intractable rather than wrong.
+    fn add(&self, other: &Linear) -> Option<Linear> {
+        let mut coefficients = Vec::with_capacity(self.coefficients.len());
+        for (x, y) in self.coefficients.iter().zip(&other.coefficients) {
+            coefficients.push(x.checked_add(*y)?);
+        }
+        Some(Linear {
+            constant: self.constant.checked_add(other.constant)?,
+            coefficients,
+        })
+    }
+
+    fn scale(&self, k: isize) -> Option<Linear> {
+        let mut coefficients = Vec::with_capacity(self.coefficients.len());
+        for x in &self.coefficients {
+            coefficients.push(x.checked_mul(k)?);
+        }
+        Some(Linear {
+            constant: self.constant.checked_mul(k)?,
+            coefficients,
+        })
+    }
+
+    fn mul(&self, other: &Linear) -> Option<Linear> {
+        match (self.as_constant(), other.as_constant()) {
+            (Some(k), _) => other.scale(k),
+            (_, Some(k)) => self.scale(k),
+            _ => None,
+        }
+    }
+
+    pub fn evaluate(&self, values: &[isize]) -> Option<isize> {
+        let mut total = self.constant;
+        for (c, x) in self.coefficients.iter().zip(values) {
+            total = total.checked_add(c.checked_mul(*x)?)?;
+        }
+        Some(total)
+    }
+}
+
+// A cell's contents: a linear expression, or something that isn't (the product of two
+// unknowns, or a value read through an unknown address). Opaque values are fine to compute
+// with as long as nothing later depends on them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Linear(Linear),
+    Opaque,
+}
+
+impl Value {
+    fn constant(&self) -> Option<isize> {
+        match self {
+            Value::Linear(linear) => linear.as_constant(),
+            Value::Opaque => None,
+        }
+    }
+
+    fn combine<F: Fn(&Linear, &Linear) -> Option<Linear>>(&self, other: &Value, f: F) -> Value {
+        match (self, other) {
+            (Value::Linear(x), Value::Linear(y)) => f(x, y).map_or(Value::Opaque, Value::Linear),
+            _ => Value::Opaque,
+        }
+    }
+}
+
+struct Machine {
+    memory: Vec<Value>,
+    unknowns: usize,
+    relative_base: isize,
+}
+
+impl Machine {
+    fn cell(&self, address: usize) -> Value {
+        match self.memory.get(address) {
+            Some(value) => value.clone(),
+            None => Value::Linear(Linear::constant(0, self.unknowns)),
+        }
+    }
+
+    fn address(&self, arg: &Arg) -> Option<usize> {
+        let address = match *arg {
+            Arg::Parameter(x) => x,
+            Arg::Relative(x) => self.relative_base.checked_add(x)?,
+            Arg::Immediate(_) => return None,
+        };
+        if address < 0 || address as usize >= MAX_ADDRESS {
+            return None;
+        }
+        Some(address as usize)
+    }
+
+    // `word` is the operand's own cell. When that isn't a constant the decoded `arg` holds a
+    // placeholder: immediates take the symbolic word itself, and reads through it are opaque.
+    fn read(&self, arg: &Arg, word: &Value) -> Option<Value> {
+        match (*arg, word.constant()) {
+            (Arg::Immediate(_), None) => Some(word.clone()),
+            (_, None) => Some(Value::Opaque),
+            (Arg::Immediate(x), Some(_)) => Some(Value::Linear(Linear::constant(x, self.unknowns))),
+            _ => Some(self.cell(self.address(arg)?)),
+        }
+    }
+
+    fn write(&mut self, arg: &Arg, word: &Value, value: Value) -> Option<()> {
+        word.constant()?;
+        let address = self.address(arg)?;
+        if address >= self.memory.len() {
+            let zero = Value::Linear(Linear::constant(0, self.unknowns));
+            self.memory.resize(address + 1, zero);
+        }
+        self.memory[address] = value;
+        Some(())
+    }
+
+    fn concrete(&self, arg: &Arg, word: &Value) -> Option<isize> {
+        self.read(arg, word)?.constant()
+    }
+
+    fn compare<F: Fn(isize) -> bool>(&self, x: &Value, y: &Value, f: F) -> Value {
+        let n = self.unknowns;
+        x.combine(y, |x, y| {
+            let difference = x.add(&y.scale(-1)?)?.as_constant()?;
+            Some(Linear::constant(f(difference) as isize, n))
+        })
+    }
+
+    fn jump(target: isize) -> Option<usize> {
+        if target < 0 || target as usize >= MAX_ADDRESS {
+            None
+        } else {
+            Some(target as usize)
+        }
+    }
+}
+
+// Runs `program` to halt with the cells at `unknowns` left symbolic, returning the final
+// contents of `result` as a linear expression over them. Returns None when that isn't
+// possible: the program does I/O, branches, writes or executes code through a value that
+// isn't a known constant, leaves a non-linear result, or doesn't halt within the step limit.
+pub fn evaluate(program: &[isize], unknowns: &[usize], result: usize) -> Option<Linear> {
+    let n = unknowns.len();
+    let mut machine = Machine {
+        memory: program
+            .iter()
+            .map(|x| Value::Linear(Linear::constant(*x, n)))
+            .collect(),
+        unknowns: n,
+        relative_base: 0,
+    };
+    let constant = Value::Linear(Linear::constant(0, n));
+    for (i, address) in unknowns.iter().enumerate() {
+        let unknown = Value::Linear(Linear::unknown(i, n));
+        machine.write(&Arg::Parameter(*address as isize), &constant, unknown)?;
+    }
+
+    let mut ip = 0;
+    for _ in 0..MAX_STEPS {
+        machine.cell(ip).constant()?;
+        let instruction =
+            Instruction::decode(ip, |a| machine.cell(a).constant().unwrap_or(0)).ok()?;
+        let args = instruction.args();
+        let words: Vec<Value> = (1..instruction.size())
+            .map(|i| machine.cell(ip + i))
+            .collect();
+        let read = |i: usize| machine.read(&args[i], &words[i]);
+        let next = ip + instruction.size();
+        let (value, target) = match instruction {
+            Instruction::Halt => {
+                return match machine.cell(result) {
+                    Value::Linear(linear) => Some(linear),
+                    Value::Opaque => None,
+                }
+            }
+            Instruction::Add(..) => (Some(read(0)?.combine(&read(1)?, Linear::add)), next),
+            Instruction::Mul(..) => (Some(read(0)?.combine(&read(1)?, Linear::mul)), next),
+            Instruction::Input(_) | Instruction::Output(_) => return None,
+            Instruction::JNZ(..) | Instruction::JZ(..) => {
+                let taken = machine.concrete(&args[0], &words[0])? != 0;
+                if taken == matches!(instruction, Instruction::JNZ(..)) {
+                    (None, Machine::jump(machine.concrete(&args[1], &words[1])?)?)
+                } else {
+                    (None, next)
+                }
+            }
+            Instruction::LessThan(..) => {
+                (Some(machine.compare(&read(0)?, &read(1)?, |d| d < 0)), next)
+            }
+            Instruction::Equals(..) => (
+                Some(machine.compare(&read(0)?, &read(1)?, |d| d == 0)),
+                next,
+            ),
+            Instruction::AdjustRelativeBase(_) => {
+                let offset = machine.concrete(&args[0], &words[0])?;
+                machine.relative_base = machine.relative_base.checked_add(offset)?;
+                (None, next)
+            }
+        };
+        if let (Some(value), Some(i)) = (value, instruction.write_arg()) {
+            machine.write(&args[i], &words[i], value)?;
+        }
+        ip = target;
+    }
+    None
+}
+
+// The first assignment, in the order a nested loop over `ranges` would visit them, for which
+// `linear` equals `target`. The last unknown is solved for directly rather than searched.
+pub fn solve(linear: &Linear, target: isize, ranges: &[Range<isize>]) -> Option<Vec<isize>> {
+    let (last, rest) = ranges.split_last()?;
+    let c = *linear.coefficients.last()?;
+    // `multi_cartesian_product` yields nothing for no ranges, where we want one empty prefix.
+    let prefixes: Box<dyn Iterator<Item = Vec<isize>>> = if rest.is_empty() {
+        Box::new(iter::once(Vec::new()))
+    } else {
+        Box::new(rest.iter().cloned().multi_cartesian_product())
+    };
+    for prefix in prefixes {
+        let mut values = prefix;
+        values.push(0);
+        let remainder = target.checked_sub(linear.evaluate(&values)?)?;
+        let x = if c == 0 {
+            if remainder != 0 || last.start >= last.end {
+                continue;
+            }
+            last.start
+        } else {
+            match (remainder.checked_rem(c), remainder.checked_div(c)) {
+                (Some(0), Some(x)) => x,
+                _ => continue,
+            }
+        };
+        if last.contains(&x) {
+            *values.last_mut().unwrap() = x;
+            return Some(values);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_symbolic() {
+    // [0] = 3 * noun + verb + 4, via a loop that adds noun three times.
+    let program = super::asm::assemble(
+        "
+        start:  ADD #0, #0, [0]
+        loop:   ADD [0], [noun], [0]
+                ADD [count], #-1, [count]
+                JNZ [count], #loop
+                ADD [0], [verb], [0]
+                ADD [0], #4, [0]
+                HLT
+        noun:   DATA 0
+        verb:   DATA 0
+        count:  DATA 3
+        ",
+    )
+    .unwrap();
+    let noun = program.len() - 3;
+    let linear = evaluate(&program, &[noun, noun + 1], 0).unwrap();
+    assert_eq!(
+        linear,
+        Linear {
+            constant: 4,
+            coefficients: vec![3, 1]
+        }
+    );
+    assert_eq!(solve(&linear, 30, &[0..10, 0..10]), Some(vec![6, 8]));
+    assert_eq!(solve(&linear, 1000, &[0..10, 0..10]), None);
+
+    // Reads through the unknowns are fine as long as their results are overwritten.
+    let program = [1, 0, 0, 0, 2, 1, 2, 3, 1, 1, 2, 0, 99];
+    assert_eq!(
+        evaluate(&program, &[1, 2], 0),
+        Some(Linear {
+            constant: 0,
+            coefficients: vec![1, 1]
+        })
+    );
+    // noun * verb is out of reach.
+    assert_eq!(evaluate(&program, &[1, 2], 3), None);
+    assert_eq!(evaluate(&[1, 0, 0, 0, 99], &[1, 2], 0), None);
+
+    // isize::MIN / -1 overflows; there's no solution rather than a panic.
+    let negate = Linear {
+        constant: 0,
+        coefficients: vec![-1],
+    };
+    let ranges = std::slice::from_ref(&(0..10));
+    assert_eq!(solve(&negate, isize::MIN, ranges), None);
+    assert_eq!(solve(&negate, -3, ranges), Some(vec![3]));
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use super::{Instruction, Word};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent<W = isize> {
+    pub ip: usize,
+    pub instruction: Instruction<W>,
+    // One entry per operand: the value read, or for the operand written through, its address.
+    pub operands: Vec<W>,
+    pub write: Option<(usize, W)>,
+}
+
+impl<W: Word> fmt::Display for TraceEvent<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}: {}", self.ip, self.instruction)?;
+        if !self.operands.is_empty() {
+            let operands: Vec<String> = self.operands.iter().map(|x| x.to_string()).collect();
+            write!(f, " ; {}", operands.join(", "))?;
+        }
+        if let Some((address, value)) = self.write {
+            write!(f, " => [{}]={}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait TraceSink<W = isize> {
+    fn trace(&mut self, event: &TraceEvent<W>);
+}
+
+impl<W: Word> TraceSink<W> for Vec<TraceEvent<W>> {
+    fn trace(&mut self, event: &TraceEvent<W>) {
+        self.push(event.clone());
+    }
+}
+
+// Lets the caller keep a handle on a sink after handing it to the VM.
+impl<W, T: TraceSink<W>> TraceSink<W> for Arc<Mutex<T>> {
+    fn trace(&mut self, event: &TraceEvent<W>) {
+        self.lock().unwrap().trace(event);
+    }
+}
+
+// One line per instruction, in the same format as `TraceEvent`'s Display.
+pub struct TextTracer<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> TextTracer<W> {
+        TextTracer { out, error: None }
+    }
+
+    // Returns the writer, or the first error hit while tracing.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => {
+                self.out.flush()?;
+                Ok(self.out)
+            }
+        }
+    }
+}
+
+impl<W: Write, V: Word> TraceSink<V> for TextTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", event) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+// Binary trace format: the header `ICTR` and a version byte, then per event the ip, the
+// encoded instruction words, the operands and an optional (address, value) write, all as
+// LEB128 varints (signed values zigzag-encoded). The write is preceded by a 0/1 flag byte.
+const MAGIC: &[u8] = b"ICTR";
+const VERSION: u8 = 1;
+
+pub struct BinaryTracer<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+    started: bool,
+}
+
+impl<W: Write> BinaryTracer<W> {
+    pub fn new(out: W) -> BinaryTracer<W> {
+        BinaryTracer {
+            out,
+            error: None,
+            started: false,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if !self.started {
+            self.header()?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        self.started = true;
+        self.out.write_all(MAGIC)?;
+        self.out.write_all(&[VERSION])
+    }
+
+    fn record(&mut self, event: &TraceEvent) -> io::Result<()> {
+        if !self.started {
+            self.header()?;
+        }
+        let mut buf = Vec::new();
+        write_unsigned(&mut buf, event.ip as u64);
+        for word in event.instruction.encode() {
+            write_signed(&mut buf, word as i64);
+        }
+        for operand in &event.operands {
+            write_signed(&mut buf, *operand as i64);
+        }
+        match event.write {
+            Some((address, value)) => {
+                buf.push(1);
+                write_unsigned(&mut buf, address as u64);
+                write_signed(&mut buf, value as i64);
+            }
+            None => buf.push(0),
+        }
+        self.out.write_all(&buf)
+    }
+}
+
+impl<W: Write> TraceSink for BinaryTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            if let Err(e) = self.record(event) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+fn write_unsigned(buf: &mut Vec<u8>, mut x: u64) {
+    loop {
+        let byte = (x & 0x7f) as u8;
+        x >>= 7;
+        if x == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_signed(buf: &mut Vec<u8>, x: i64) {
+    write_unsigned(buf, ((x << 1) ^ (x >> 63)) as u64);
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| invalid("truncated trace"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn unsigned(&mut self) -> io::Result<u64> {
+        let mut x = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            x |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    fn signed(&mut self) -> io::Result<isize> {
+        let x = self.unsigned()?;
+        Ok(((x >> 1) as i64 ^ -((x & 1) as i64)) as isize)
+    }
+}
+
+pub fn read_binary_trace<R: Read>(mut input: R) -> io::Result<Vec<TraceEvent>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    if data.len() < MAGIC.len() + 1 || &data[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not an Intcode binary trace"));
+    }
+    if data[MAGIC.len()] != VERSION {
+        return Err(invalid("unsupported trace version"));
+    }
+    let mut reader = Reader {
+        data: &data,
+        pos: MAGIC.len() + 1,
+    };
+    let mut events = Vec::new();
+    while reader.pos < data.len() {
+        let ip = reader.unsigned()? as usize;
+        let mut words = vec![reader.signed()?];
+        let size = Instruction::decode(0, |a| words.get(a).cloned().unwrap_or(0))
+            .map_err(|_| invalid("bad instruction in trace"))?
+            .size();
+        for _ in 1..size {
+            words.push(reader.signed()?);
+        }
+        let instruction = Instruction::decode(0, |a| words[a]).unwrap();
+        let mut operands = Vec::new();
+        for _ in 0..instruction.arg_count() {
+            operands.push(reader.signed()?);
+        }
+        let write = match reader.byte()? {
+            0 => None,
+            1 => Some((reader.unsigned()? as usize, reader.signed()?)),
+            _ => return Err(invalid("bad write flag in trace")),
+        };
+        events.push(TraceEvent {
+            ip,
+            instruction,
+            operands,
+            write,
+        });
+    }
+    Ok(events)
+}
+
+#[test]
+fn test_trace() {
+    use super::IntCode;
+
+    let program = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let text = Arc::new(Mutex::new(TextTracer::new(Vec::new())));
+    let binary = Arc::new(Mutex::new(BinaryTracer::new(Vec::new())));
+
+    for sink in [
+        Box::new(events.clone()) as Box<dyn TraceSink + Send>,
+        Box::new(text.clone()),
+        Box::new(binary.clone()),
+    ] {
+        let mut machine = IntCode::new(&program);
+        machine.set_tracer(sink);
+        machine.push_input(8);
+        assert_eq!(machine.run_to_halt().unwrap(), vec![1]);
+    }
+
+    let events = events.lock().unwrap().clone();
+    assert_eq!(events.len(), 4);
+    assert_eq!(
+        events[1],
+        TraceEvent {
+            ip: 2,
+            instruction: Instruction::Equals(
+                super::Arg::Parameter(9),
+                super::Arg::Parameter(10),
+                super::Arg::Parameter(9)
+            ),
+            operands: vec![8, 8, 9],
+            write: Some((9, 1)),
+        }
+    );
+
+    let text = Arc::try_unwrap(text).ok().unwrap().into_inner().unwrap();
+    assert_eq!(
+        String::from_utf8(text.finish().unwrap()).unwrap(),
+        "0000: IN [9] ; 9 => [9]=8\n\
+         0002: EQ [9], [10], [9] ; 8, 8, 9 => [9]=1\n\
+         0006: OUT [9] ; 1\n\
+         0008: HLT\n"
+    );
+
+    let binary = Arc::try_unwrap(binary).ok().unwrap().into_inner().unwrap();
+    let bytes = binary.finish().unwrap();
+    assert_eq!(read_binary_trace(&bytes[..]).unwrap(), events);
+}
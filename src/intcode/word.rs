@@ -0,0 +1,58 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+// A machine word. Instructions, addresses and the relative base stay `isize`; only the values
+// computed with and stored in memory take the word type.
+pub trait Word:
+    Copy + Default + fmt::Debug + fmt::Display + FromStr + Ord + Hash + Send + Sync + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    // Truncates if `x` doesn't fit.
+    fn from_isize(x: isize) -> Self;
+    fn to_isize(self) -> Option<isize>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                const ZERO: $t = 0;
+                const ONE: $t = 1;
+
+                fn from_isize(x: isize) -> $t {
+                    x as $t
+                }
+
+                fn to_isize(self) -> Option<isize> {
+                    isize::try_from(self).ok()
+                }
+
+                fn checked_add(self, other: $t) -> Option<$t> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn checked_mul(self, other: $t) -> Option<$t> {
+                    <$t>::checked_mul(self, other)
+                }
+
+                fn wrapping_add(self, other: $t) -> $t {
+                    <$t>::wrapping_add(self, other)
+                }
+
+                fn wrapping_mul(self, other: $t) -> $t {
+                    <$t>::wrapping_mul(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(i32, i64, i128, isize);
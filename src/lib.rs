@@ -1,5 +1,9 @@
 use aoc_runner_derive::{aoc, aoc_generator, aoc_lib};
 
+pub mod intcode;
+
+use intcode::{symbolic, IntCode, Network, NetworkState, QueueIo, RunState, Topology};
+
 #[aoc_generator(day1)]
 pub fn input_generator_day1(input: &str) -> Vec<i64> {
     input.lines().map(|x| x.trim().parse().unwrap()).collect()
@@ -32,51 +36,75 @@ fn test_day1() {
 }
 
 #[aoc_generator(day2)]
-pub fn input_generator_day2(input: &str) -> Vec<usize> {
+pub fn input_generator_day2(input: &str) -> Vec<isize> {
     input
         .split(',')
         .map(|x| x.trim().parse().unwrap())
         .collect()
 }
 
-fn day2(input: &[usize], a: usize, b: usize) -> usize {
-    let mut i = 0usize;
-    let mut v = input.to_vec();
-    v[1] = a;
-    v[2] = b;
-    loop {
-        match v[i] {
-            99 => break,
-            1 => {
-                let (x, y, z) = (v[i + 1], v[i + 2], v[i + 3]);
-                v[z] = v[x] + v[y]
-            }
-            2 => {
-                let (x, y, z) = (v[i + 1], v[i + 2], v[i + 3]);
-                v[z] = v[x] * v[y]
-            }
-            _ => unimplemented!(),
-        }
-        i += 4;
-    }
-    v[0]
+fn day2(machine: &mut IntCode, a: isize, b: isize) -> isize {
+    machine.reset();
+    machine.patch(&[(1, a), (2, b)]).unwrap();
+    machine.run_to_halt().unwrap();
+    machine.peek(0).unwrap()
 }
 
 #[aoc(day2, part1)]
-pub fn solve_day2_part1(input: &[usize]) -> usize {
-    day2(input, 12, 2)
+pub fn solve_day2_part1(input: &[isize]) -> isize {
+    day2(&mut IntCode::new(input), 12, 2)
 }
 
-#[aoc(day2, part2)]
-pub fn solve_day2_part2(input: &[usize]) -> usize {
-    for x in 0..input.len() {
-        for y in 0..input.len() {
-            if day2(input, x, y) == 19690720 {
-                return 100 * x + y;
+fn day2_search(input: &[isize], target: isize) -> Option<isize> {
+    let len = input.len() as isize;
+    let mut machine = IntCode::new(input);
+    for x in 0..len {
+        for y in 0..len {
+            if day2(&mut machine, x, y) == target {
+                return Some(100 * x + y);
             }
         }
     }
-    0
+    None
+}
+
+// Solves for the verb directly when the output is linear in the noun and verb.
+fn day2_symbolic(input: &[isize], target: isize) -> Option<isize> {
+    let len = input.len() as isize;
+    let linear = symbolic::evaluate(input, &[1, 2], 0)?;
+    let values = symbolic::solve(&linear, target, &[0..len, 0..len])?;
+    Some(100 * values[0] + values[1])
+}
+
+#[aoc(day2, part2)]
+pub fn solve_day2_part2(input: &[isize]) -> isize {
+    day2_symbolic(input, 19690720)
+        .or_else(|| day2_search(input, 19690720))
+        .unwrap_or(0)
+}
+
+#[test]
+fn test_day2() {
+    let mut machine = IntCode::new(&input_generator_day2("1,9,10,3,2,3,11,0,99,30,40,50"));
+    machine.run_to_halt().unwrap();
+    assert_eq!(machine.memory()[0], 3500);
+}
+
+#[test]
+fn test_day2_symbolic() {
+    // [0] = 5 * noun + verb + 7, with a throwaway read through the noun and verb first.
+    let program = [
+        1, 0, 0, 3, 2, 1, 18, 17, 1, 17, 2, 0, 1, 0, 19, 0, 99, 0, 5, 7,
+    ];
+    assert_eq!(day2_symbolic(&program, 76), Some(1019));
+    assert_eq!(day2_search(&program, 76), Some(1019));
+    assert_eq!(day2_symbolic(&program, 1000), None);
+    assert_eq!(day2_search(&program, 1000), None);
+
+    // noun * verb isn't linear, so only the search finds it.
+    let program = [1, 0, 0, 3, 2, 1, 2, 0, 99];
+    assert_eq!(day2_symbolic(&program, 12), None);
+    assert_eq!(day2_search(&program, 12), Some(206));
 }
 
 use std::collections::HashSet;
@@ -129,6 +157,7 @@ fn day3_fill_points(directions: &[String]) -> HashSet<(i64, i64)> {
 }
 
 #[aoc(day3, part1)]
+#[allow(clippy::legacy_numeric_constants)]
 pub fn solve_day3_part1(input: &(Vec<String>, Vec<String>)) -> i64 {
     let one = day3_fill_points(&input.0);
     let two = day3_fill_points(&input.1);
@@ -191,6 +220,7 @@ fn day3_count_steps(directions: &[String], target: &(i64, i64)) -> i64 {
 }
 
 #[aoc(day3, part2)]
+#[allow(clippy::legacy_numeric_constants)]
 pub fn solve_day3_part2(input: &(Vec<String>, Vec<String>)) -> i64 {
     let one = day3_fill_points(&input.0);
     let two = day3_fill_points(&input.1);
@@ -215,6 +245,7 @@ pub fn input_generator_day4(_input: &str) -> Vec<usize> {
     Vec::new()
 }
 
+#[allow(clippy::assign_op_pattern, clippy::manual_contains)]
 fn day4(val: i32, part2: bool) -> bool {
     let mut digits = Vec::new();
     let mut x = val;
@@ -251,6 +282,7 @@ pub fn solve_day4_part1(_input: &[usize]) -> usize {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_day4() {
     assert_eq!(day4(111111, false), true);
     assert_eq!(day4(223450, false), false);
@@ -272,129 +304,12 @@ pub fn solve_day4_part2(_input: &[usize]) -> usize {
     count
 }
 
-/*
-fn fetch1(v: &[isize], ip: usize, inst: isize) -> isize {
-    let x = v[ip + 1];
-    let x = match (inst / 100) % 10 {
-        0 => v[x as usize],
-        1 => x,
-        _ => unimplemented!(),
-    };
-    x
-}
-
-fn fetch2(v: &[isize], ip: usize, inst: isize) -> (isize, isize) {
-    let x = fetch1(v, ip, inst);
-    let y = fetch1(v, ip + 1, inst / 10);
-    (x, y)
-}
-
-fn fetch3(v: &[isize], ip: usize, inst: isize) -> (isize, isize, isize) {
-    let x = fetch1(v, ip, inst);
-    let y = fetch1(v, ip + 1, inst / 10);
-    let z = fetch1(v, ip + 2, inst / 100);
-    (x, y, z)
-}
-*/
-
 fn day5(input: &[isize], inp: Vec<isize>) -> Vec<isize> {
-    let mut out = Vec::new();
-    let mut i = 0usize;
-    let mut v = input.to_vec();
-    let mut inp_index = 0;
-    loop {
-        match v[i] {
-            99 => break,
-            a if a % 100 == 1 => {
-                let (x, y, z) = (v[i + 1], v[i + 2], v[i + 3]);
-                let mode0 = (a / 100) % 10;
-                let mode1 = (a / 1000) % 10;
-                let x = if mode0 == 1 { x } else { v[x as usize] };
-                let y = if mode1 == 1 { y } else { v[y as usize] };
-                v[z as usize] = x + y;
-                i += 4;
-            }
-            a if a % 100 == 2 => {
-                let (x, y, z) = (v[i + 1], v[i + 2], v[i + 3]);
-                let mode0 = (a / 100) % 10;
-                let mode1 = (a / 1000) % 10;
-                let x = if mode0 == 1 { x } else { v[x as usize] };
-                let y = if mode1 == 1 { y } else { v[y as usize] };
-                v[z as usize] = x * y;
-                i += 4;
-            }
-            a if a % 100 == 3 => {
-                let mode0 = (a / 100) % 10;
-                assert_eq!(mode0, 0);
-                let x = v[i + 1];
-                v[x as usize] = inp[inp_index];
-                inp_index += 1;
-                i += 2;
-            }
-            a if a % 100 == 4 => {
-                let mode0 = (a / 100) % 10;
-                let x = v[i + 1];
-                if mode0 == 1 {
-                    out.push(x);
-                } else {
-                    out.push(v[x as usize]);
-                }
-                i += 2;
-            }
-            a if a % 100 == 5 => {
-                let (x, y) = (v[i + 1], v[i + 2]);
-                let mode0 = (a / 100) % 10;
-                let mode1 = (a / 1000) % 10;
-                let x = if mode0 == 1 { x } else { v[x as usize] };
-                let y = if mode1 == 1 { y } else { v[y as usize] };
-                if x != 0 {
-                    i = y as usize;
-                } else {
-                    i += 3;
-                }
-            }
-            a if a % 100 == 6 => {
-                let (x, y) = (v[i + 1], v[i + 2]);
-                let mode0 = (a / 100) % 10;
-                let mode1 = (a / 1000) % 10;
-                let x = if mode0 == 1 { x } else { v[x as usize] };
-                let y = if mode1 == 1 { y } else { v[y as usize] };
-                if x == 0 {
-                    i = y as usize;
-                } else {
-                    i += 3;
-                }
-            }
-            a if a % 100 == 7 => {
-                let (x, y, z) = (v[i + 1], v[i + 2], v[i + 3]);
-                let mode0 = (a / 100) % 10;
-                let mode1 = (a / 1000) % 10;
-                let x = if mode0 == 1 { x } else { v[x as usize] };
-                let y = if mode1 == 1 { y } else { v[y as usize] };
-                if x < y {
-                    v[z as usize] = 1;
-                } else {
-                    v[z as usize] = 0;
-                }
-                i += 4;
-            }
-            a if a % 100 == 8 => {
-                let (x, y, z) = (v[i + 1], v[i + 2], v[i + 3]);
-                let mode0 = (a / 100) % 10;
-                let mode1 = (a / 1000) % 10;
-                let x = if mode0 == 1 { x } else { v[x as usize] };
-                let y = if mode1 == 1 { y } else { v[y as usize] };
-                if x == y {
-                    v[z as usize] = 1;
-                } else {
-                    v[z as usize] = 0;
-                }
-                i += 4;
-            }
-            _ => unimplemented!(),
-        }
-    }
-    out
+    let mut io = QueueIo::new(&inp);
+    // Running out of input before halting is an error.
+    let state = IntCode::new(input).run_io(&mut io);
+    assert_eq!(state, Ok(RunState::Halted));
+    io.output
 }
 
 #[aoc_generator(day5)]
@@ -417,6 +332,22 @@ pub fn solve_day5_part2(input: &[isize]) -> isize {
     *ret.last().unwrap()
 }
 
+#[test]
+fn test_day5() {
+    let program = input_generator_day5(
+        "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+    );
+    assert_eq!(day5(&program, vec![7]), vec![999]);
+    assert_eq!(day5(&program, vec![8]), vec![1000]);
+    assert_eq!(day5(&program, vec![9]), vec![1001]);
+}
+
+#[test]
+#[should_panic]
+fn test_day5_missing_input() {
+    day5(&input_generator_day5("3,0,4,0,99"), vec![]);
+}
+
 #[aoc_generator(day6)]
 pub fn input_generator_day6(input: &str) -> Vec<(String, String)> {
     let mut y = Vec::new();
@@ -462,6 +393,7 @@ fn chain_orbits(map: &[(String, String)], this: &str) -> Vec<String> {
 }
 
 #[aoc(day6, part2)]
+#[allow(clippy::legacy_numeric_constants)]
 pub fn solve_day6_part2(input: &[(String, String)]) -> usize {
     let you = chain_orbits(input, "YOU");
     let san = chain_orbits(input, "SAN");
@@ -505,201 +437,90 @@ pub fn input_generator_day7(input: &str) -> Vec<isize> {
 }
 
 use itertools::Itertools;
+use std::ops::Range;
 
-#[aoc(day7, part1)]
-pub fn solve_day7_part1(input: &[isize]) -> isize {
-    let mut max = isize::min_value();
-    for x in (0..5).permutations(5) {
-        let a = day5(input, vec![x[0], 0]);
-        let b = day5(input, vec![x[1], a[0]]);
-        let c = day5(input, vec![x[2], b[0]]);
-        let d = day5(input, vec![x[3], c[0]]);
-        let e = day5(input, vec![x[4], d[0]]);
-        max = std::cmp::max(max, e[0]);
+// None if the amplifiers fail or stall rather than halting with a signal.
+fn day7(network: &mut Network, phases: &[isize]) -> Option<isize> {
+    network.reset();
+    for (node, phase) in phases.iter().enumerate() {
+        network.push_input(node, *phase);
     }
-    max
-}
-
-//mod intcode;
-
-#[derive(Default)]
-struct Day7State {
-    input: Vec<isize>,
-    output: Vec<isize>,
-    memory: Vec<isize>,
-    i: usize,
-    inp_index: usize,
-}
-
-fn day7_step(s: &mut Day7State) -> bool {
-    let v = &mut s.memory;
-    match v[s.i] {
-        99 => return false,
-        a if a % 100 == 1 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 { x } else { v[x as usize] };
-            let y = if mode1 == 1 { y } else { v[y as usize] };
-            v[z as usize] = x + y;
-            s.i += 4;
-        }
-        a if a % 100 == 2 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 { x } else { v[x as usize] };
-            let y = if mode1 == 1 { y } else { v[y as usize] };
-            v[z as usize] = x * y;
-            s.i += 4;
-        }
-        a if a % 100 == 3 => {
-            if s.inp_index >= s.input.len() {
-                // Stall waiting for input.
-                return true;
-            }
-            let mode0 = (a / 100) % 10;
-            assert_eq!(mode0, 0);
-            let x = v[s.i + 1];
-            v[x as usize] = s.input[s.inp_index];
-            s.inp_index += 1;
-            s.i += 2;
-        }
-        a if a % 100 == 4 => {
-            let mode0 = (a / 100) % 10;
-            let x = v[s.i + 1];
-            if mode0 == 1 {
-                s.output.push(x);
-            } else {
-                s.output.push(v[x as usize]);
-            }
-            s.i += 2;
-        }
-        a if a % 100 == 5 => {
-            let (x, y) = (v[s.i + 1], v[s.i + 2]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 { x } else { v[x as usize] };
-            let y = if mode1 == 1 { y } else { v[y as usize] };
-            if x != 0 {
-                s.i = y as usize;
-            } else {
-                s.i += 3;
-            }
-        }
-        a if a % 100 == 6 => {
-            let (x, y) = (v[s.i + 1], v[s.i + 2]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 { x } else { v[x as usize] };
-            let y = if mode1 == 1 { y } else { v[y as usize] };
-            if x == 0 {
-                s.i = y as usize;
-            } else {
-                s.i += 3;
-            }
-        }
-        a if a % 100 == 7 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 { x } else { v[x as usize] };
-            let y = if mode1 == 1 { y } else { v[y as usize] };
-            if x < y {
-                v[z as usize] = 1;
-            } else {
-                v[z as usize] = 0;
-            }
-            s.i += 4;
-        }
-        a if a % 100 == 8 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 { x } else { v[x as usize] };
-            let y = if mode1 == 1 { y } else { v[y as usize] };
-            if x == y {
-                v[z as usize] = 1;
-            } else {
-                v[z as usize] = 0;
-            }
-            s.i += 4;
-        }
-        _ => unimplemented!(),
+    network.push_input(0, 0);
+    match network.run() {
+        Ok(NetworkState::Halted) => network.last_output(network.len() - 1),
+        _ => None,
     }
-    true
 }
 
-#[aoc(day7, part2)]
-pub fn solve_day7_part2(input: &[isize]) -> isize {
-    let mut max = isize::min_value();
-    for x in (5..10).permutations(5) {
-        let mut a = Day7State::default();
-        let mut b = Day7State::default();
-        let mut c = Day7State::default();
-        let mut d = Day7State::default();
-        let mut e = Day7State::default();
-        a.memory = input.to_vec();
-        b.memory = input.to_vec();
-        c.memory = input.to_vec();
-        d.memory = input.to_vec();
-        e.memory = input.to_vec();
-        a.input = vec![x[0], 0];
-        b.input = vec![x[1]];
-        c.input = vec![x[2]];
-        d.input = vec![x[3]];
-        e.input = vec![x[4]];
-        let mut processing = vec![true; 5];
-        while processing.iter().any(|x| *x) {
-            if processing[0] {
-                let i = a.output.len();
-                processing[0] = day7_step(&mut a);
-                if a.output.len() != i {
-                    for val in i..a.output.len() {
-                        b.input.push(a.output[val]);
-                    }
-                }
-            }
-            if processing[1] {
-                let i = b.output.len();
-                processing[1] = day7_step(&mut b);
-                if b.output.len() != i {
-                    for val in i..b.output.len() {
-                        c.input.push(b.output[val]);
-                    }
-                }
-            }
-            if processing[2] {
-                let i = c.output.len();
-                processing[2] = day7_step(&mut c);
-                if c.output.len() != i {
-                    for val in i..c.output.len() {
-                        d.input.push(c.output[val]);
-                    }
-                }
-            }
-            if processing[3] {
-                let i = d.output.len();
-                processing[3] = day7_step(&mut d);
-                if d.output.len() != i {
-                    for val in i..d.output.len() {
-                        e.input.push(d.output[val]);
-                    }
-                }
-            }
-            if processing[4] {
-                let i = e.output.len();
-                processing[4] = day7_step(&mut e);
-                if e.output.len() != i {
-                    for val in i..e.output.len() {
-                        a.input.push(e.output[val]);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhaseSearch {
+    pub signal: isize,
+    pub phases: Vec<isize>,
+}
+
+// Tries every ordering of `amplifiers` distinct phases drawn from `phases`, split across all
+// cores. Ties go to the ordering that comes first in permutation order.
+pub fn day7_search(
+    input: &[isize],
+    phases: Range<isize>,
+    amplifiers: usize,
+    topology: Topology,
+) -> Option<PhaseSearch> {
+    if amplifiers == 0 {
+        return None;
+    }
+    let candidates: Vec<Vec<isize>> = phases.permutations(amplifiers).collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = candidates.len().div_ceil(threads);
+    let best = std::thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let topology = topology.clone();
+                scope.spawn(move || {
+                    let machines = (0..amplifiers).map(|_| IntCode::new(input)).collect();
+                    let mut network = Network::new(machines, topology);
+                    let mut best: Option<(isize, &Vec<isize>)> = None;
+                    for x in chunk {
+                        if let Some(signal) = day7(&mut network, x) {
+                            if best.is_none_or(|(b, _)| signal > b) {
+                                best = Some((signal, x));
+                            }
+                        }
                     }
+                    best
+                })
+            })
+            .collect();
+        let mut best: Option<(isize, &Vec<isize>)> = None;
+        for handle in handles {
+            if let Some((signal, x)) = handle.join().unwrap() {
+                if best.is_none_or(|(b, _)| signal > b) {
+                    best = Some((signal, x));
                 }
             }
         }
-        max = std::cmp::max(max, *e.output.last().unwrap());
-    }
-    max
+        best
+    });
+    best.map(|(signal, phases)| PhaseSearch {
+        signal,
+        phases: phases.clone(),
+    })
+}
+
+#[aoc(day7, part1)]
+pub fn solve_day7_part1(input: &[isize]) -> isize {
+    day7_search(input, 0..5, 5, Topology::Pipeline)
+        .unwrap()
+        .signal
+}
+
+#[aoc(day7, part2)]
+pub fn solve_day7_part2(input: &[isize]) -> isize {
+    day7_search(input, 5..10, 5, Topology::Ring).unwrap().signal
 }
 
 #[test]
@@ -710,6 +531,27 @@ fn day7_test() {
             "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5"
         ))
     );
+    assert_eq!(
+        43210,
+        solve_day7_part1(&input_generator_day7(
+            "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0"
+        ))
+    );
+    let program = input_generator_day7(
+        "3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0",
+    );
+    assert_eq!(
+        day7_search(&program, 0..5, 5, Topology::Pipeline),
+        Some(PhaseSearch {
+            signal: 54321,
+            phases: vec![0, 1, 2, 3, 4]
+        })
+    );
+    assert_eq!(day7_search(&program, 0..3, 4, Topology::Pipeline), None);
+    assert_eq!(day7_search(&program, 0..5, 0, Topology::Pipeline), None);
+    // Wants a third input that never comes.
+    let program = input_generator_day7("3,9,3,9,3,9,4,9,99,0");
+    assert_eq!(day7_search(&program, 0..5, 2, Topology::Pipeline), None);
 }
 
 #[aoc_generator(day8)]
@@ -766,234 +608,23 @@ pub fn input_generator_day9(input: &str) -> Vec<isize> {
         .collect()
 }
 
-#[derive(Default)]
-struct Day9State {
-    input: Vec<isize>,
-    output: Vec<isize>,
-    memory: Vec<isize>,
-    i: usize,
-    inp_index: usize,
-    relative_base: isize,
-}
-
-fn day9_step(s: &mut Day9State) -> bool {
-    let v = &mut s.memory;
-    match v[s.i] {
-        99 => return false,
-        a if a % 100 == 1 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let mode2 = (a / 10000) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            let y = if mode1 == 1 {
-                y
-            } else if mode1 == 2 {
-                v[(y + s.relative_base) as usize]
-            } else {
-                v[y as usize]
-            };
-            let z = if mode2 == 0 { z } else { z + s.relative_base };
-            v[z as usize] = x + y;
-            s.i += 4;
-        }
-        a if a % 100 == 2 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let mode2 = (a / 10000) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            let y = if mode1 == 1 {
-                y
-            } else if mode1 == 2 {
-                v[(y + s.relative_base) as usize]
-            } else {
-                v[y as usize]
-            };
-            let z = if mode2 == 0 { z } else { z + s.relative_base };
-            v[z as usize] = x * y;
-            s.i += 4;
-        }
-        a if a % 100 == 3 => {
-            if s.inp_index >= s.input.len() {
-                // Stall waiting for input.
-                return true;
-            }
-            let mode0 = (a / 100) % 10;
-            let x = v[s.i + 1];
-            let x = if mode0 == 0 { x } else { x + s.relative_base };
-            v[x as usize] = s.input[s.inp_index];
-            s.inp_index += 1;
-            s.i += 2;
-        }
-        a if a % 100 == 4 => {
-            let mode0 = (a / 100) % 10;
-            let x = v[s.i + 1];
-            if mode0 == 1 {
-                s.output.push(x);
-            } else if mode0 == 2 {
-                s.output.push(v[(x + s.relative_base) as usize]);
-            } else {
-                s.output.push(v[x as usize]);
-            }
-            s.i += 2;
-        }
-        a if a % 100 == 5 => {
-            let (x, y) = (v[s.i + 1], v[s.i + 2]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            let y = if mode1 == 1 {
-                y
-            } else if mode1 == 2 {
-                v[(y + s.relative_base) as usize]
-            } else {
-                v[y as usize]
-            };
-            if x != 0 {
-                s.i = y as usize;
-            } else {
-                s.i += 3;
-            }
-        }
-        a if a % 100 == 6 => {
-            let (x, y) = (v[s.i + 1], v[s.i + 2]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            let y = if mode1 == 1 {
-                y
-            } else if mode1 == 2 {
-                v[(y + s.relative_base) as usize]
-            } else {
-                v[y as usize]
-            };
-            if x == 0 {
-                s.i = y as usize;
-            } else {
-                s.i += 3;
-            }
-        }
-        a if a % 100 == 7 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let mode2 = (a / 10000) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            let y = if mode1 == 1 {
-                y
-            } else if mode1 == 2 {
-                v[(y + s.relative_base) as usize]
-            } else {
-                v[y as usize]
-            };
-            let z = if mode2 == 0 { z } else { z + s.relative_base };
-            if x < y {
-                v[z as usize] = 1;
-            } else {
-                v[z as usize] = 0;
-            }
-            s.i += 4;
-        }
-        a if a % 100 == 8 => {
-            let (x, y, z) = (v[s.i + 1], v[s.i + 2], v[s.i + 3]);
-            let mode0 = (a / 100) % 10;
-            let mode1 = (a / 1000) % 10;
-            let mode2 = (a / 10000) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            let y = if mode1 == 1 {
-                y
-            } else if mode1 == 2 {
-                v[(y + s.relative_base) as usize]
-            } else {
-                v[y as usize]
-            };
-            let z = if mode2 == 0 { z } else { z + s.relative_base };
-            if x == y {
-                v[z as usize] = 1;
-            } else {
-                v[z as usize] = 0;
-            }
-            s.i += 4;
-        }
-        a if a % 100 == 9 => {
-            let x = v[s.i + 1];
-            let mode0 = (a / 100) % 10;
-            let x = if mode0 == 1 {
-                x
-            } else if mode0 == 2 {
-                v[(x + s.relative_base) as usize]
-            } else {
-                v[x as usize]
-            };
-            s.relative_base += x;
-            s.i += 2;
-        }
-        _ => unimplemented!(),
-    }
-    true
+fn day9(input: &[isize], inp: isize) -> isize {
+    let mut machine = IntCode::new(input);
+    machine.set_trap_overflow(true);
+    machine.push_input(inp);
+    let output = machine.run_to_halt().unwrap();
+    assert!(output.len() == 1);
+    output[0]
 }
 
 #[aoc(day9, part1)]
 pub fn solve_day9_part1(input: &[isize]) -> isize {
-    let mut a = Day9State::default();
-    a.memory = input.to_vec();
-    a.memory.extend(vec![0; 1000]);
-    a.input = vec![1];
-    while day9_step(&mut a) {
-        //
-    }
-    assert!(a.output.len() == 1);
-    a.output[0]
+    day9(input, 1)
 }
 
 #[aoc(day9, part2)]
 pub fn solve_day9_part2(input: &[isize]) -> isize {
-    let mut a = Day9State::default();
-    a.memory = input.to_vec();
-    a.memory.extend(vec![0; 1000]);
-    a.input = vec![2];
-    while day9_step(&mut a) {
-        //
-    }
-    assert!(a.output.len() == 1);
-    a.output[0]
+    day9(input, 2)
 }
 
 aoc_lib! { year = 2019 }